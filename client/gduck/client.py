@@ -5,17 +5,45 @@ from dataclasses import dataclass
 from pathlib import Path
 from queue import SimpleQueue
 from types import TracebackType
-from typing import Generator, Self
+from typing import Generator, Iterable, Self
 
 import grpc
 from grpc._channel import _MultiThreadedRendezvous
 
 from .exceptions import GduckRpcError, GduckServerError
+from .proto.append_pb2 import EndAppend
+from .proto.location_pb2 import Location
 from .proto.query_pb2 import Query
 from .proto.service_pb2 import Request, Response
 from .proto.service_pb2_grpc import DbServiceStub
-from .request import ConnectionMode, Value, connect, ctas, execute, local_file, parquet, request, rows, value
-from .response import parse_location, parse_rows, parse_value
+from .proto.statement_pb2 import ClosePrepared, Prepare
+from .proto.transaction_pb2 import Begin, Cancel, Commit, Rollback
+from .request import (
+    ConnectionMode,
+    ExportFormat,
+    Value,
+    append,
+    append_rows,
+    arrow,
+    close_prepared,
+    connect,
+    ctas,
+    end_append,
+    execute,
+    execute_batch,
+    execute_prepared,
+    export,
+    load_arrow,
+    local_file,
+    parquet,
+    prepare,
+    request,
+    rows,
+    stream_rows,
+    value,
+)
+from .response import parse_location, parse_row_batch, parse_rows, parse_schema, parse_value
+from .types import Exported, FileLocation
 
 __all__ = ["Addr", "Connection", "DuckDbTransaction"]
 
@@ -40,8 +68,14 @@ class Addr:
 class Connection:
     addr: Addr
 
-    def transaction(self, database_file: str, mode: ConnectionMode) -> DuckDbTransaction:
-        return DuckDbTransaction(self.addr, database_file=database_file, mode=mode)
+    def transaction(
+        self,
+        database_file: str,
+        mode: ConnectionMode,
+        settings: dict[str, str] | None = None,
+        storages: list[Location] | None = None,
+    ) -> DuckDbTransaction:
+        return DuckDbTransaction(self.addr, database_file=database_file, mode=mode, settings=settings, storages=storages)
 
 
 class ResponseHandlerThread(threading.Thread):
@@ -68,10 +102,19 @@ class DuckDbTransaction:
 
     _END_STREAM = "END_STREAM"
 
-    def __init__(self, addr: Addr, database_file: str, mode: ConnectionMode) -> None:
+    def __init__(
+        self,
+        addr: Addr,
+        database_file: str,
+        mode: ConnectionMode,
+        settings: dict[str, str] | None = None,
+        storages: list[Location] | None = None,
+    ) -> None:
         self._addr = addr
         self._database_file = database_file
         self._mode = mode
+        self._settings = settings
+        self._storages = storages
 
         self._requests = SimpleQueue()
         self._results = SimpleQueue()
@@ -83,32 +126,119 @@ class DuckDbTransaction:
         while (request := self._requests.get()) != self._END_STREAM:
             yield request
 
-    def _query(self, query: Query) -> Response.QueryResult:
-        self._requests.put(request(query))
+    def _next_result(self) -> Response.QueryResult:
         result = self._results.get()
         if isinstance(result, Exception):
             raise result
         else:
             return result
 
-    def execute(self, query: str, *params: tuple[Value]) -> None:
-        self._query(execute(query, *params))
+    def _query(self, query: Query | Begin | Commit | Rollback | Prepare | ClosePrepared | EndAppend) -> Response.QueryResult:
+        self._requests.put(request(query))
+        return self._next_result()
+
+    def begin(self) -> None:
+        self._query(Begin())
+
+    def commit(self) -> None:
+        self._query(Commit())
+
+    def rollback(self) -> None:
+        self._query(Rollback())
+
+    def execute(self, query: str, *params: tuple[Value], **named_params: Value) -> None:
+        self._query(execute(query, *params, **named_params))
 
-    def query_value(self, query: str, *params: tuple[Value]) -> Value:
-        result = self._query(value(query, *params))
+    def execute_batch(self, query: str, params: list[tuple[Value]], in_transaction: bool = False) -> list[int]:
+        result = self._query(execute_batch(query, params, in_transaction=in_transaction))
+        return list(result.executed_batch.num_rows)
+
+    def query_value(self, query: str, *params: tuple[Value], **named_params: Value) -> Value:
+        result = self._query(value(query, *params, **named_params))
         return parse_value(result.value)
 
-    def query_rows(self, query: str, *params: tuple[Value]) -> Value:
-        result = self._query(rows(query, *params))
+    def query_rows(self, query: str, *params: tuple[Value], **named_params: Value) -> Value:
+        result = self._query(rows(query, *params, **named_params))
         _, r = parse_rows(result.rows)
         return r
 
-    def ctas(self, table_name: str, query: str, *params: tuple[Value]) -> None:
-        self._query(ctas(table_name, query, *params))
+    def stream_rows(self, query: str, *params: tuple[Value], batch_size: int = 0, **named_params: Value) -> Generator[tuple[Value], None, None]:
+        result = self._query(stream_rows(query, *params, batch_size=batch_size, **named_params))
+        _, getters = parse_schema(result.schema)
+        try:
+            while not (result := self._next_result()).HasField("end_of_rows"):
+                yield from parse_row_batch(getters, result.row_batch)
+        except GeneratorExit:
+            # Rows left unread would otherwise be taken as the results of the next request.
+            self._requests.put(request(Cancel()))
+            self._skip_rows()
+            raise
+
+    def _skip_rows(self) -> None:
+        while not isinstance(result := self._results.get(), Exception) and not result.HasField("end_of_rows"):
+            pass
+
+    def query_arrow(self, query: str, *params: tuple[Value], **named_params: Value) -> bytes:
+        result = self._query(arrow(query, *params, **named_params))
+        chunks = []
+        while not result.HasField("end_of_rows"):
+            chunks.append(result.arrow_ipc)
+            result = self._next_result()
+        return b"".join(chunks)
+
+    def load_arrow(self, table_name: str, arrow_ipc: bytes, create_temporary: bool = False, replace: bool = False) -> int:
+        result = self._query(load_arrow(table_name, arrow_ipc, create_temporary=create_temporary, replace=replace))
+        return result.appended.num_rows
+
+    def ctas(self, table_name: str, query: str, *params: tuple[Value], **named_params: Value) -> None:
+        self._query(ctas(table_name, query, *params, **named_params))
+
+    def local_parquet(self, file: Path, query: str, *params: tuple[Value], **named_params: Value) -> Path:
+        # Local files are exported to local paths.
+        return Path(self.parquet(local_file(file), query, *params, **named_params))
+
+    def parquet(self, location: Location, query: str, *params: tuple[Value], **named_params: Value) -> FileLocation:
+        result = self._query(parquet(location, query, *params, **named_params))
+        return parse_location(result.exported.location)
+
+    def export(
+        self, location: Location, query: str, *params: tuple[Value], format: ExportFormat | None = None, **named_params: Value
+    ) -> Exported:
+        result = self._query(export(location, query, *params, format=format, **named_params))
+        return Exported(location=parse_location(result.exported.location), num_rows=result.exported.num_rows)
+
+    def prepare(self, query: str) -> int:
+        result = self._query(prepare(query))
+        return result.prepared.handle
+
+    def execute_prepared(self, handle: int, *params: tuple[Value], **named_params: Value) -> None:
+        self._query(execute_prepared(handle, Query.ExecutePrepared.OUTPUT_EXECUTE, *params, **named_params))
+
+    def query_prepared_value(self, handle: int, *params: tuple[Value], **named_params: Value) -> Value:
+        result = self._query(execute_prepared(handle, Query.ExecutePrepared.OUTPUT_VALUE, *params, **named_params))
+        return parse_value(result.value)
+
+    def query_prepared_rows(self, handle: int, *params: tuple[Value], **named_params: Value) -> Value:
+        result = self._query(execute_prepared(handle, Query.ExecutePrepared.OUTPUT_ROWS, *params, **named_params))
+        _, r = parse_rows(result.rows)
+        return r
 
-    def local_parquet(self, file: Path, query: str, *params: tuple[Value]) -> Path:
-        result = self._query(parquet(local_file(file), query, *params))
-        return parse_location(result.parquet_file)
+    def close_prepared(self, handle: int) -> None:
+        self._query(close_prepared(handle))
+
+    def append(self, table_name: str, rows: Iterable[tuple[Value]], batch_size: int = 1024, schema_name: str = "") -> int:
+        # Only EndAppend gets a response, which reports the first failure if any.
+        self._requests.put(request(append(table_name, schema_name=schema_name)))
+        batch = []
+        for row in rows:
+            batch.append(row)
+            if len(batch) >= batch_size:
+                self._requests.put(request(append_rows(batch)))
+                batch = []
+        if batch:
+            self._requests.put(request(append_rows(batch)))
+        result = self._query(end_append())
+        return result.appended.num_rows
 
     def __enter__(self) -> Self:
         self._channel = grpc.insecure_channel(target=str(self._addr))
@@ -126,4 +256,4 @@ class DuckDbTransaction:
         return False
 
     def _connect_request(self) -> Request:
-        return request(kind=connect(file_name=self._database_file, mode=self._mode))
+        return request(kind=connect(file_name=self._database_file, mode=self._mode, settings=self._settings, storages=self._storages))
@@ -1,22 +1,39 @@
-import math
+import calendar
 from datetime import date, datetime, time
 from decimal import Decimal
 from pathlib import Path
-from typing import Literal
+from typing import Literal, TypeAlias
+from uuid import UUID
 
 from dateutil.relativedelta import relativedelta
 from google.protobuf.struct_pb2 import NULL_VALUE
 from google.protobuf.timestamp_pb2 import Timestamp
 
+from .proto.append_pb2 import Append, AppendRows, EndAppend
 from .proto.database_pb2 import Connect, Date
 from .proto.database_pb2 import Decimal as ProtoDecimal
-from .proto.database_pb2 import Interval, Params, ScalarValue, Time
+from .proto.database_pb2 import (
+    HugeInt,
+    Interval,
+    ListValue,
+    MapValue,
+    Params,
+    Row,
+    RowBatch,
+    ScalarValue,
+    StructValue,
+    Time,
+    TimeTz,
+    UHugeInt,
+)
 from .proto.location_pb2 import Location
 from .proto.query_pb2 import Query
 from .proto.service_pb2 import Request
-from .types import Value
+from .proto.statement_pb2 import ClosePrepared, Prepare
+from .proto.transaction_pb2 import Begin, Cancel, Commit, Rollback
+from .types import Map, Struct, Value
 
-__all__ = ["ConnectionMode", "connect", "local_file", "execute", "value", "rows", "ctas", "parquet", "request"]
+__all__ = ["ConnectionMode", "connect", "local_file", "s3_object", "gcs_object", "azure_blob", "execute", "value", "rows", "stream_rows", "arrow", "ctas", "ExportFormat", "export", "parquet", "csv_format", "json_format", "load_arrow", "execute_batch", "prepare", "execute_prepared", "close_prepared", "append", "append_rows", "end_append", "request"]
 
 ConnectionMode = Literal["auto", "read_write", "read_only"]
 
@@ -30,8 +47,23 @@ def _mode(m: ConnectionMode = Connect.Mode.MODE_AUTO) -> Connect.Mode:
         return Connect.Mode.MODE_AUTO
 
 
-def connect(file_name: str, mode: ConnectionMode) -> Connect:
-    return Connect(file_name=file_name, mode=_mode(mode))
+def connect(
+    file_name: str,
+    mode: ConnectionMode,
+    settings: dict[str, str] | None = None,
+    storages: list[Location] | None = None,
+) -> Connect:
+    return Connect(file_name=file_name, mode=_mode(mode), settings=settings or {}, storages=storages or [])
+
+
+def _timestamp(v: datetime) -> Timestamp:
+    # A naive datetime is sent as it is, i.e. as if it were in UTC.
+    seconds = calendar.timegm(v.utctimetuple())
+    return Timestamp(seconds=seconds, nanos=v.microsecond * 1000)
+
+
+def _time(v: time) -> Time:
+    return Time(hours=v.hour, minutes=v.minute, seconds=v.second, nanos=v.microsecond * 1000)
 
 
 def _value(v: Value) -> ScalarValue:
@@ -40,20 +72,32 @@ def _value(v: Value) -> ScalarValue:
     elif type(v) is bool:
         return ScalarValue(bool_value=v)
     elif type(v) is int:
-        return ScalarValue(int_value=v)
+        if -(2**63) <= v < 2**63:
+            return ScalarValue(int_value=v)
+        elif -(2**127) <= v < 2**127:
+            return ScalarValue(hugeint_value=HugeInt(upper=v >> 64, lower=v & (2**64 - 1)))
+        elif 0 <= v < 2**128:
+            return ScalarValue(uhugeint_value=UHugeInt(upper=v >> 64, lower=v & (2**64 - 1)))
+        else:
+            raise ValueError(f"Integer out of the range of HUGEINT and UHUGEINT: {v}")
     elif type(v) is float:
         return ScalarValue(double_value=v)
     elif type(v) is Decimal:
-        return ScalarValue(decimal_value=ProtoDecimal(value=str(v)))
+        return ScalarValue(decimal_value=ProtoDecimal(value=format(v, "f")))
     elif type(v) is str:
         return ScalarValue(str_value=v)
+    elif type(v) is bytes:
+        return ScalarValue(bytes_value=v)
+    elif type(v) is datetime and v.tzinfo is not None:
+        return ScalarValue(timestamptz_value=_timestamp(v))
     elif type(v) is datetime:
-        fraction, seconds = math.modf(v.timestamp())
-        return ScalarValue(datetime_value=Timestamp(seconds=int(seconds), nanos=int(fraction * 1000000000)))
+        return ScalarValue(datetime_value=_timestamp(v))
     elif type(v) is date:
         return ScalarValue(date_value=Date(year=v.year, month=v.month, day=v.day))
+    elif type(v) is time and v.utcoffset() is not None:
+        return ScalarValue(timetz_value=TimeTz(time=_time(v), offset_seconds=int(v.utcoffset().total_seconds())))
     elif type(v) is time:
-        return ScalarValue(time_value=Time(hours=v.hour, minutes=v.minute, seconds=v.second, nanos=v.microsecond * 1000))
+        return ScalarValue(time_value=_time(v))
     elif type(v) is relativedelta:
         nv = v.normalized()
         return ScalarValue(
@@ -63,42 +107,191 @@ def _value(v: Value) -> ScalarValue:
                 nanos=1000000000 * (nv.hours * 3600 + nv.minutes * 60 + nv.seconds) + 1000 * nv.microseconds,
             )
         )
+    elif type(v) is UUID:
+        return ScalarValue(uuid_value=str(v))
+    elif type(v) is list:
+        return ScalarValue(list_value=ListValue(values=[_value(e) for e in v]))
+    elif type(v) is Struct and not v:
+        raise ValueError("STRUCT must have fields")
+    elif type(v) is Struct:
+        return ScalarValue(struct_value=StructValue(fields=[StructValue.Field(name=k, value=_value(e)) for k, e in v.items()]))
+    # A plain dict is a MAP whatever its keys are, so that its type does not depend on them.
+    elif type(v) is Map or type(v) is dict:
+        return ScalarValue(map_value=MapValue(entries=[MapValue.Entry(key=_value(k), value=_value(e)) for k, e in v.items()]))
     else:
         raise ValueError(f"Invalid type of value: {v} ({type(v)})")
 
 
-def _params(*params: tuple[Value]) -> Params:
-    return Params(params=[_value(p) for p in params])
+def _params(*params: tuple[Value], **named_params: Value) -> Params:
+    return Params(params=[_value(p) for p in params], named_params={k: _value(v) for k, v in named_params.items()})
 
 
 def local_file(path: Path) -> Location:
     return Location(local=Location.LocalFile(path=str(path)))
 
 
-def execute(query: str, *params: tuple[Value]) -> Query:
-    return Query(execute=Query.Execute(query=query, params=_params(*params)))
+def s3_object(
+    bucket: str,
+    key: str,
+    region: str = "",
+    endpoint: str = "",
+    path_style: bool = False,
+    disable_ssl: bool = False,
+    credential: str = "",
+) -> Location:
+    return Location(
+        s3=Location.S3Object(
+            bucket=bucket,
+            key=key,
+            region=region,
+            endpoint=endpoint,
+            path_style=path_style,
+            disable_ssl=disable_ssl,
+            credential=credential,
+        )
+    )
+
+
+def gcs_object(bucket: str, key: str, credential: str = "") -> Location:
+    return Location(gcs=Location.GcsObject(bucket=bucket, key=key, credential=credential))
+
+
+def azure_blob(
+    container: str,
+    path: str,
+    account_name: str = "",
+    data_lake: bool = False,
+    credential: str = "",
+) -> Location:
+    return Location(
+        azure=Location.AzureBlob(
+            account_name=account_name,
+            container=container,
+            path=path,
+            data_lake=data_lake,
+            credential=credential,
+        )
+    )
+
+
+def execute(query: str, *params: tuple[Value], **named_params: Value) -> Query:
+    return Query(execute=Query.Execute(query=query, params=_params(*params, **named_params)))
+
+
+def value(query: str, *params: tuple[Value], **named_params: Value) -> Query:
+    return Query(value=Query.QueryValue(query=query, params=_params(*params, **named_params)))
+
+
+def rows(query: str, *params: tuple[Value], **named_params: Value) -> Query:
+    return Query(rows=Query.QueryRows(query=query, params=_params(*params, **named_params)))
+
+
+def stream_rows(query: str, *params: tuple[Value], batch_size: int = 0, **named_params: Value) -> Query:
+    return Query(stream_rows=Query.StreamRows(query=query, params=_params(*params, **named_params), batch_size=batch_size))
+
+
+def arrow(query: str, *params: tuple[Value], **named_params: Value) -> Query:
+    return Query(arrow=Query.QueryArrow(query=query, params=_params(*params, **named_params)))
+
+
+def ctas(table_name: str, query: str, *params: tuple[Value], **named_params: Value) -> Query:
+    return Query(ctas=Query.CreateTableAsQuery(table_name=table_name, query=query, params=_params(*params, **named_params)))
+
+
+ExportFormat: TypeAlias = Query.ExportQuery.Parquet | Query.ExportQuery.Csv | Query.ExportQuery.Json
+
+
+def csv_format(delimiter: str = "", header: bool | None = None, quote: str = "", null_string: str = "") -> Query.ExportQuery.Csv:
+    return Query.ExportQuery.Csv(delimiter=delimiter, header=header, quote=quote, null_string=null_string)
+
+
+def json_format(array: bool = False) -> Query.ExportQuery.Json:
+    # NDJSON unless array
+    return Query.ExportQuery.Json(array=array)
+
+
+def export(location: Location, query: str, *params: tuple[Value], format: ExportFormat | None = None, **named_params: Value) -> Query:
+    export_query = Query.ExportQuery(location=location, query=query, params=_params(*params, **named_params))
+    if isinstance(format, Query.ExportQuery.Csv):
+        export_query.csv.CopyFrom(format)
+    elif isinstance(format, Query.ExportQuery.Json):
+        export_query.json.CopyFrom(format)
+    else:
+        export_query.parquet.SetInParent()
+    return Query(export=export_query)
+
+
+def parquet(location: Location, query: str, *params: tuple[Value], **named_params: Value) -> Query:
+    return export(location, query, *params, **named_params)
+
+
+def load_arrow(table_name: str, arrow_ipc: bytes, create_temporary: bool = False, replace: bool = False) -> Query:
+    return Query(
+        load_arrow=Query.LoadArrow(
+            table_name=table_name, arrow_ipc=arrow_ipc, create_temporary=create_temporary, replace=replace
+        )
+    )
+
+
+# The params always run in a transaction of their own unless one is open, so in_transaction is ignored.
+def execute_batch(query: str, params: list[tuple[Value]], in_transaction: bool = False) -> Query:
+    return Query(execute_batch=Query.ExecuteBatch(query=query, params=[_params(*p) for p in params]))
+
+
+def prepare(query: str) -> Prepare:
+    return Prepare(query=query)
+
+
+def execute_prepared(
+    handle: int, output: Query.ExecutePrepared.Output, *params: tuple[Value], batch_size: int = 0, **named_params: Value
+) -> Query:
+    return Query(
+        execute_prepared=Query.ExecutePrepared(
+            handle=handle, params=_params(*params, **named_params), output=output, batch_size=batch_size
+        )
+    )
 
 
-def value(query: str, *params: tuple[Value]) -> Query:
-    return Query(value=Query.QueryValue(query=query, params=_params(*params)))
+def close_prepared(handle: int) -> ClosePrepared:
+    return ClosePrepared(handle=handle)
 
 
-def rows(query: str, *params: tuple[Value]) -> Query:
-    return Query(rows=Query.QueryRows(query=query, params=_params(*params)))
+def append(table_name: str, schema_name: str = "") -> Append:
+    return Append(table_name=table_name, schema_name=schema_name)
 
 
-def ctas(table_name: str, query: str, *params: tuple[Value]) -> Query:
-    return Query(ctas=Query.CreateTableAsQuery(table_name=table_name, query=query, params=_params(*params)))
+def append_rows(rows: list[tuple[Value]]) -> AppendRows:
+    return AppendRows(rows=RowBatch(rows=[Row(values=[_value(v) for v in row]) for row in rows]))
 
 
-def parquet(location: Location, query: str, *params: tuple[Value]) -> Query:
-    return Query(parquet=Query.ParquetQuery(location=location, query=query, params=_params(*params)))
+def end_append() -> EndAppend:
+    return EndAppend()
 
 
-def request(kind: Connect | Query) -> Request:
+def request(
+    kind: Connect | Query | Begin | Commit | Rollback | Cancel | Prepare | ClosePrepared | Append | AppendRows | EndAppend,
+) -> Request:
     if type(kind) is Connect:
         return Request(connect=kind)
     elif type(kind) is Query:
         return Request(query=kind)
+    elif type(kind) is Begin:
+        return Request(begin=kind)
+    elif type(kind) is Commit:
+        return Request(commit=kind)
+    elif type(kind) is Rollback:
+        return Request(rollback=kind)
+    elif type(kind) is Cancel:
+        return Request(cancel=kind)
+    elif type(kind) is Prepare:
+        return Request(prepare=kind)
+    elif type(kind) is ClosePrepared:
+        return Request(close_prepared=kind)
+    elif type(kind) is Append:
+        return Request(append=kind)
+    elif type(kind) is AppendRows:
+        return Request(append_rows=kind)
+    elif type(kind) is EndAppend:
+        return Request(end_append=kind)
     else:
         raise ValueError(f"unsupported type of message: {kind}")
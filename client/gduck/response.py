@@ -1,17 +1,19 @@
 from __future__ import annotations
 
-from datetime import date, datetime, time
+from datetime import date, datetime, time, timedelta, timezone
 from decimal import Decimal
+from uuid import UUID
 from pathlib import Path
 from typing import Callable
 
 from dateutil.relativedelta import relativedelta
 
-from .proto.database_pb2 import DataType, Rows, ScalarValue
+from .proto.database_pb2 import DataType, Row, RowBatch, Rows, ScalarValue
+from .proto.database_pb2 import Schema as ProtoSchema
 from .proto.location_pb2 import Location
-from .types import ParquetLocation, Schema, Value
+from .types import FileLocation, Map, Schema, Struct, Value
 
-__all__ = ["parse_value", "parse_rows", "parse_location"]
+__all__ = ["parse_value", "parse_rows", "parse_schema", "parse_row_batch", "parse_location"]
 
 
 def _null_value(v: ScalarValue) -> None:
@@ -30,6 +32,14 @@ def _uint_value(v: ScalarValue) -> int:
     return v.uint_value
 
 
+def _hugeint_value(v: ScalarValue) -> int:
+    return (v.hugeint_value.upper << 64) | v.hugeint_value.lower
+
+
+def _uhugeint_value(v: ScalarValue) -> int:
+    return (v.uhugeint_value.upper << 64) | v.uhugeint_value.lower
+
+
 def _double_value(v: ScalarValue) -> float:
     return v.double_value
 
@@ -42,10 +52,22 @@ def _str_value(v: ScalarValue) -> str:
     return v.str_value
 
 
+def _bytes_value(v: ScalarValue) -> bytes:
+    return v.bytes_value
+
+
+def _uuid_value(v: ScalarValue) -> UUID:
+    return UUID(v.uuid_value)
+
+
 def _datetime_value(v: ScalarValue) -> datetime:
     return v.datetime_value.ToDatetime()
 
 
+def _timestamptz_value(v: ScalarValue) -> datetime:
+    return v.timestamptz_value.ToDatetime(tzinfo=timezone.utc)
+
+
 def _date_value(v: ScalarValue) -> date:
     dt = v.date_value
     return date(year=dt.year, month=dt.month, day=dt.day)
@@ -58,6 +80,17 @@ def _time_value(v: ScalarValue) -> time:
     )  # FIXME python time does not support nanosecond precision
 
 
+def _timetz_value(v: ScalarValue) -> time:
+    t = v.timetz_value.time
+    return time(
+        hour=t.hours,
+        minute=t.minutes,
+        second=t.seconds,
+        microsecond=int(t.nanos / 1000),
+        tzinfo=timezone(timedelta(seconds=v.timetz_value.offset_seconds)),
+    )
+
+
 def _interval_value(v: ScalarValue) -> relativedelta:
     interval = v.interval_value
     return relativedelta(
@@ -65,6 +98,26 @@ def _interval_value(v: ScalarValue) -> relativedelta:
     )  # FIXME python relativedelta does not support nanosecond precision
 
 
+def _list_value(v: ScalarValue) -> list[Value]:
+    return [parse_value(e) for e in v.list_value.values]
+
+
+def _array_value(v: ScalarValue) -> list[Value]:
+    return [parse_value(e) for e in v.array_value.values]
+
+
+def _struct_value(v: ScalarValue) -> Struct:
+    return Struct((f.name, parse_value(f.value)) for f in v.struct_value.fields)
+
+
+def _map_value(v: ScalarValue) -> Map:
+    return Map((parse_value(e.key), parse_value(e.value)) for e in v.map_value.entries)
+
+
+def _union_value(v: ScalarValue) -> Value:
+    return parse_value(v.union_value.value)
+
+
 def _getter(data_type: DataType) -> Callable[[ScalarValue], Value]:
     if data_type == DataType.DATATYPE_NULL:
         return _null_value
@@ -72,20 +125,41 @@ def _getter(data_type: DataType) -> Callable[[ScalarValue], Value]:
         return _bool_value
     elif data_type in (DataType.DATATYPE_INT, DataType.DATATYPE_UINT):
         return _int_value
+    elif data_type == DataType.DATATYPE_HUGEINT:
+        return _hugeint_value
+    elif data_type == DataType.DATATYPE_UHUGEINT:
+        return _uhugeint_value
     elif data_type == DataType.DATATYPE_DOUBLE:
         return _double_value
     elif data_type == DataType.DATATYPE_DECIMAL:
         return _decimal_value
-    elif data_type == DataType.DATATYPE_STRING:
+    elif data_type in (DataType.DATATYPE_STRING, DataType.DATATYPE_ENUM):
         return _str_value
+    elif data_type in (DataType.DATATYPE_BLOB, DataType.DATATYPE_BIT):
+        return _bytes_value
     elif data_type == DataType.DATATYPE_DATETIME:
         return _datetime_value
+    elif data_type == DataType.DATATYPE_TIMESTAMP_TZ:
+        return _timestamptz_value
     elif data_type == DataType.DATATYPE_DATE:
         return _date_value
     elif data_type == DataType.DATATYPE_TIME:
         return _time_value
+    elif data_type == DataType.DATATYPE_TIME_TZ:
+        return _timetz_value
     elif data_type == DataType.DATATYPE_INTERVAL:
         return _interval_value
+    elif data_type == DataType.DATATYPE_UUID:
+        return _uuid_value
+    elif data_type in (
+        DataType.DATATYPE_LIST,
+        DataType.DATATYPE_ARRAY,
+        DataType.DATATYPE_STRUCT,
+        DataType.DATATYPE_MAP,
+        DataType.DATATYPE_UNION,
+    ):
+        # NULL is sent as null_value, so nested values are parsed by their kind.
+        return parse_value
     else:
         raise ValueError(f"unknown data type: {data_type}")
 
@@ -99,24 +173,50 @@ def parse_value(v: ScalarValue) -> Value:
         return _int_value(v)
     elif v.HasField("uint_value"):
         return _uint_value(v)
+    elif v.HasField("hugeint_value"):
+        return _hugeint_value(v)
+    elif v.HasField("uhugeint_value"):
+        return _uhugeint_value(v)
     elif v.HasField("double_value"):
         return _double_value(v)
     elif v.HasField("decimal_value"):
         return _decimal_value(v)
     elif v.HasField("str_value"):
         return _str_value(v)
+    elif v.HasField("bytes_value"):
+        return _bytes_value(v)
     elif v.HasField("datetime_value"):
         return _datetime_value(v)
+    elif v.HasField("timestamptz_value"):
+        return _timestamptz_value(v)
     elif v.HasField("date_value"):
         return _date_value(v)
     elif v.HasField("time_value"):
         return _time_value(v)
+    elif v.HasField("timetz_value"):
+        return _timetz_value(v)
     elif v.HasField("interval_value"):
         return _interval_value(v)
+    elif v.HasField("list_value"):
+        return _list_value(v)
+    elif v.HasField("array_value"):
+        return _array_value(v)
+    elif v.HasField("struct_value"):
+        return _struct_value(v)
+    elif v.HasField("map_value"):
+        return _map_value(v)
+    elif v.HasField("uuid_value"):
+        return _uuid_value(v)
+    elif v.HasField("union_value"):
+        return _union_value(v)
     else:
         raise ValueError(f"unknown type of value {v}")
 
 
+def _parse_row(getters: list[Callable[[ScalarValue], Value]], row: Row) -> tuple[Value]:
+    return tuple(getters[i](v) for i, v in enumerate(row.values))
+
+
 def parse_rows(rows: Rows) -> tuple[Schema, list[tuple[Value]]]:
     schema = Schema.from_proto(rows.schema)
 
@@ -124,11 +224,26 @@ def parse_rows(rows: Rows) -> tuple[Schema, list[tuple[Value]]]:
 
     ret = []
     for row in rows.rows:
-        values = tuple(getters[i](v) for i, v in enumerate(row.values))
-        ret.append(values)
+        ret.append(_parse_row(getters, row))
 
     return schema, ret
 
 
-def parse_location(location: Location) -> ParquetLocation:
+def parse_schema(schema: ProtoSchema) -> tuple[Schema, list[Callable[[ScalarValue], Value]]]:
+    s = Schema.from_proto(schema)
+    return s, [_getter(col.data_type) for col in s]
+
+
+def parse_row_batch(getters: list[Callable[[ScalarValue], Value]], batch: RowBatch) -> list[tuple[Value]]:
+    return [_parse_row(getters, row) for row in batch.rows]
+
+
+def parse_location(location: Location) -> FileLocation:
+    if location.HasField("s3"):
+        return f"s3://{location.s3.bucket}/{location.s3.key}"
+    if location.HasField("gcs"):
+        return f"gs://{location.gcs.bucket}/{location.gcs.key}"
+    if location.HasField("azure"):
+        scheme = "abfss" if location.azure.data_lake else "az"
+        return f"{scheme}://{location.azure.container}/{location.azure.path}"
     return Path(location.local.path)
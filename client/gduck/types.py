@@ -1,28 +1,63 @@
-from dataclasses import dataclass
+from dataclasses import dataclass, field
 from datetime import date, datetime, time
 from decimal import Decimal
 from pathlib import Path
 from typing import Iterator, Self, TypeAlias
+from uuid import UUID
 
 from dateutil.relativedelta import relativedelta
 
 from .proto.database_pb2 import Column as ProtoColumn
+from .proto.database_pb2 import ColumnType
 from .proto.database_pb2 import DataType
 from .proto.database_pb2 import Schema as ProtoSchema
 
-__all__ = ["Value", "Column", "Schema", "Rows", "ParquetLocation"]
+__all__ = ["Value", "Struct", "Map", "Column", "Schema", "Rows", "FileLocation", "ParquetLocation", "Exported"]
 
-Value: TypeAlias = bool | int | float | Decimal | str | datetime | date | time | relativedelta | None
+
+class Struct(dict[str, "Value"]):
+    """STRUCT of which fields are the items in their order.
+
+    Params are bound as STRUCT only when wrapped in Struct, as a plain dict is bound as a MAP.
+    """
+
+
+class Map(dict["Value", "Value"]):
+    """MAP of which keys can be of any type, which is how a plain dict is bound as well."""
+
+
+Value: TypeAlias = (
+    bool
+    | int
+    | float
+    | Decimal
+    | str
+    | bytes
+    | datetime
+    | date
+    | time
+    | relativedelta
+    | UUID
+    | list["Value"]
+    | Struct
+    | Map
+    | dict["Value", "Value"]
+    | None
+)
 
 
 @dataclass(frozen=True)
 class Column:
     name: str
     data_type: DataType
+    # Name of the type in DuckDB, e.g. "DECIMAL(18,3)"
+    type_name: str = ""
+    # Detailed type including the types of elements of nested types
+    column_type: ColumnType | None = field(default=None, compare=False)
 
     @classmethod
     def from_proto(cls, col: ProtoColumn) -> Self:
-        return cls(name=col.name, data_type=col.data_type)
+        return cls(name=col.name, data_type=col.data_type, type_name=col.column_type.type_name, column_type=col.column_type)
 
 
 @dataclass(frozen=True)
@@ -53,4 +88,14 @@ class Rows:
         return iter(self.rows)
 
 
-ParquetLocation: TypeAlias = Path
+# Path of a local file or URL of an object in a storage
+FileLocation: TypeAlias = Path | str
+
+# Former name of FileLocation, kept for compatibility
+ParquetLocation: TypeAlias = FileLocation
+
+
+@dataclass(frozen=True)
+class Exported:
+    location: FileLocation
+    num_rows: int
@@ -3,24 +3,85 @@ from typing import Generator
 import pytest
 from gduck.client import Addr, Connection, DuckDbTransaction
 from testcontainers.core.container import DockerContainer
-from testcontainers.core.waiting_utils import wait_for
+from testcontainers.core.network import Network
+from testcontainers.core.waiting_utils import wait_for, wait_for_logs
 
 IMAGE_NAME = "gduck:latest"
 CONTAINER_PORT = 50051
 
+MINIO_IMAGE_NAME = "minio/minio:latest"
+MINIO_USER = "gduck"
+MINIO_PASSWORD = "gduckgduck"
+# Bucket created in minio, which gduck reaches at "minio:9000" with credential "minio"
+MINIO_BUCKET = "gduck"
+
+
+def _connection(container: DockerContainer) -> Connection:
+    host = container.get_container_host_ip()
+    port = container.get_exposed_port(CONTAINER_PORT)
+    return Connection(addr=Addr(host=host, port=port))
+
+
+def _wait_healthy(container: DockerContainer) -> None:
+    wait_for(lambda: container.get_wrapped_container() is not None and container.get_wrapped_container().health == "healthy")
+
 
 @pytest.fixture(scope="function")
 def gduck_container(image_name: str = IMAGE_NAME) -> Generator[DockerContainer, None, None]:
     with DockerContainer(image_name).with_exposed_ports(CONTAINER_PORT) as dc:
-        wait_for(lambda: dc.get_wrapped_container() is not None and dc.get_wrapped_container().health == "healthy")
+        _wait_healthy(dc)
         yield dc
 
 
 @pytest.fixture(scope="function")
 def gduck_connection(gduck_container: DockerContainer) -> Connection:
-    host = gduck_container.get_container_host_ip()
-    port = gduck_container.get_exposed_port(CONTAINER_PORT)
-    return Connection(addr=Addr(host=host, port=port))
+    return _connection(gduck_container)
+
+
+@pytest.fixture(scope="function")
+def minio_network() -> Generator[Network, None, None]:
+    with Network() as network:
+        yield network
+
+
+@pytest.fixture(scope="function")
+def minio_container(minio_network: Network) -> Generator[DockerContainer, None, None]:
+    container = (
+        DockerContainer(MINIO_IMAGE_NAME)
+        .with_command("server /data")
+        .with_env("MINIO_ROOT_USER", MINIO_USER)
+        .with_env("MINIO_ROOT_PASSWORD", MINIO_PASSWORD)
+        .with_network(minio_network)
+        .with_network_aliases("minio")
+    )
+    with container as dc:
+        wait_for_logs(dc, "API:")
+        created = dc.exec(
+            ["sh", "-c", f"mc alias set local http://localhost:9000 {MINIO_USER} {MINIO_PASSWORD} && mc mb local/{MINIO_BUCKET}"]
+        )
+        assert created.exit_code == 0, created.output
+        yield dc
+
+
+@pytest.fixture(scope="function")
+def minio_bucket(minio_container: DockerContainer) -> str:
+    return MINIO_BUCKET
+
+
+@pytest.fixture(scope="function")
+def gduck_minio_connection(minio_network: Network, minio_container: DockerContainer) -> Generator[Connection, None, None]:
+    container = (
+        DockerContainer(IMAGE_NAME)
+        .with_exposed_ports(CONTAINER_PORT)
+        .with_env("GDUCK_CREDENTIAL_MINIO_KEY_ID", MINIO_USER)
+        .with_env("GDUCK_CREDENTIAL_MINIO_SECRET", MINIO_PASSWORD)
+        .with_env("GDUCK_CREDENTIAL_MINIO_SCOPE", f"s3://{MINIO_BUCKET}/")
+        .with_env("GDUCK_CREDENTIAL_MINIO_ENDPOINT", "minio:9000")
+        .with_network(minio_network)
+    )
+    with container as dc:
+        _wait_healthy(dc)
+        yield _connection(dc)
 
 @pytest.fixture(scope="function")
 def gduck_in_memory_rw_connection(gduck_connection: Connection) -> Generator[DuckDbTransaction, None, None]:
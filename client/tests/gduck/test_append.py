@@ -0,0 +1,7 @@
+from gduck.client import DuckDbTransaction
+
+
+def test_append(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    gduck_in_memory_rw_connection.execute("CREATE TABLE t (i INTEGER, s VARCHAR);")
+    assert gduck_in_memory_rw_connection.append("t", ((i, str(i)) for i in range(3000)), batch_size=1000) == 3000
+    assert gduck_in_memory_rw_connection.query_value("SELECT COUNT(DISTINCT s) FROM t;") == 3000
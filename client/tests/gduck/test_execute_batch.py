@@ -0,0 +1,15 @@
+import pytest
+from gduck.client import DuckDbTransaction
+from gduck.exceptions import GduckServerError
+
+
+def test_execute_batch(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    gduck_in_memory_rw_connection.execute("CREATE TABLE t (i INTEGER);")
+    assert gduck_in_memory_rw_connection.execute_batch("INSERT INTO t VALUES (?);", [(1,), (2,), (3,)]) == [1, 1, 1]
+    assert gduck_in_memory_rw_connection.query_value("SELECT SUM(i) FROM t;") == 6
+
+def test_execute_batch_in_transaction(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    gduck_in_memory_rw_connection.execute("CREATE TABLE t (i INTEGER PRIMARY KEY);")
+    with pytest.raises(GduckServerError):
+        gduck_in_memory_rw_connection.execute_batch("INSERT INTO t VALUES (?);", [(1,), (2,), (1,)])
+    assert gduck_in_memory_rw_connection.query_value("SELECT COUNT(*) FROM t;") == 0
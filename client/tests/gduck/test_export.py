@@ -0,0 +1,17 @@
+from pathlib import Path
+
+from gduck.client import DuckDbTransaction
+from gduck.request import csv_format, local_file
+
+
+def test_export_parquet(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    file = Path("/tmp/gduck_test_export.parquet")
+    exported = gduck_in_memory_rw_connection.export(local_file(file), "SELECT i FROM range(10) t(i);")
+    assert exported.num_rows == 10
+    assert gduck_in_memory_rw_connection.query_value(f"SELECT SUM(i) FROM read_parquet('{file}');") == 45
+
+def test_export_csv(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    file = Path("/tmp/gduck_test_export.csv")
+    exported = gduck_in_memory_rw_connection.export(local_file(file), "SELECT 1 AS a, 'x' AS b;", format=csv_format(delimiter="|"))
+    assert exported.num_rows == 1
+    assert gduck_in_memory_rw_connection.query_rows(f"SELECT * FROM read_csv('{file}', delim = '|');") == [(1, "x")]
@@ -0,0 +1,13 @@
+from gduck.client import DuckDbTransaction
+
+
+def test_load_arrow_into_temporary_table(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    arrow_ipc = gduck_in_memory_rw_connection.query_arrow("SELECT i, i::VARCHAR AS s FROM range(5000) t(i);")
+    assert gduck_in_memory_rw_connection.load_arrow("loaded", arrow_ipc, create_temporary=True) == 5000
+    assert gduck_in_memory_rw_connection.query_value("SELECT SUM(i) FROM loaded;") == sum(range(5000))
+
+def test_load_arrow_into_table(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    gduck_in_memory_rw_connection.execute("CREATE TABLE loaded (i BIGINT, s VARCHAR);")
+    arrow_ipc = gduck_in_memory_rw_connection.query_arrow("SELECT i, i::VARCHAR AS s FROM range(3) t(i);")
+    assert gduck_in_memory_rw_connection.load_arrow("loaded", arrow_ipc) == 3
+    assert gduck_in_memory_rw_connection.query_rows("SELECT * FROM loaded ORDER BY i;") == [(0, "0"), (1, "1"), (2, "2")]
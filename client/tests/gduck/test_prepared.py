@@ -0,0 +1,19 @@
+import pytest
+from gduck.client import DuckDbTransaction
+from gduck.exceptions import GduckServerError
+
+
+def test_prepared(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    handle = gduck_in_memory_rw_connection.prepare("SELECT $a * $b;")
+    assert gduck_in_memory_rw_connection.query_prepared_value(handle, a=2, b=3) == 6
+    assert gduck_in_memory_rw_connection.query_prepared_rows(handle, a=4, b=5) == [(20,)]
+    gduck_in_memory_rw_connection.close_prepared(handle)
+    with pytest.raises(GduckServerError):
+        gduck_in_memory_rw_connection.query_prepared_value(handle, a=2, b=3)
+
+def test_execute_prepared(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    gduck_in_memory_rw_connection.execute("CREATE TABLE t (i INTEGER);")
+    handle = gduck_in_memory_rw_connection.prepare("INSERT INTO t VALUES (?);")
+    gduck_in_memory_rw_connection.execute_prepared(handle, 1)
+    gduck_in_memory_rw_connection.execute_prepared(handle, 2)
+    assert gduck_in_memory_rw_connection.query_value("SELECT SUM(i) FROM t;") == 3
@@ -0,0 +1,7 @@
+from gduck.client import DuckDbTransaction
+
+
+def test_query_arrow(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    actual = gduck_in_memory_rw_connection.query_arrow("SELECT i, i::VARCHAR AS s FROM range(3) t(i);")
+    # Messages of an Arrow IPC stream start with a continuation marker.
+    assert actual.startswith(b"\xff\xff\xff\xff")
@@ -1,6 +1,7 @@
 from decimal import Decimal
 
 from gduck.client import DuckDbTransaction
+from gduck.types import Map, Struct
 from pytest_benchmark.fixture import BenchmarkFixture
 
 
@@ -18,4 +19,13 @@ def test_select_1_1(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
     assert type(actual) is Decimal
 
 def test_select_1_1_bench(benchmark: BenchmarkFixture, gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
-    benchmark(gduck_in_memory_rw_connection.query_value, "SELECT 1.1;")
\ No newline at end of file
+    benchmark(gduck_in_memory_rw_connection.query_value, "SELECT 1.1;")
+def test_nested_params_with_quotes_and_commas(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    strings = ["c,d", "it's", '["x"]', "NULL", None]
+    for param in [strings, Struct({'a:b, "c"': "{'x': 1}, y=z", "l": strings}), Map({"k,'1'=": " v, 2 "})]:
+        actual = gduck_in_memory_rw_connection.query_value("SELECT $v;", v=param)
+        assert actual == param
+        assert type(actual) is type(param)
+
+def test_empty_dict_as_map(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    assert gduck_in_memory_rw_connection.query_value("SELECT cardinality($m::MAP(VARCHAR, INTEGER));", m={}) == 0
@@ -0,0 +1,29 @@
+import pytest
+from gduck.request import _value
+from gduck.types import Map, Struct
+
+
+def test_struct_only_when_wrapped() -> None:
+    assert _value(Struct({"a": 1, "b_2": "x"})).HasField("struct_value")
+    assert _value(Struct({"a b": 1})).HasField("struct_value")
+
+
+def test_dict_as_map() -> None:
+    assert _value({}).HasField("map_value")
+    assert _value({"en": "hi"}).HasField("map_value")
+    assert _value({"en-US": "hi"}).HasField("map_value")
+    assert _value({1: "x"}).HasField("map_value")
+    assert _value(Map({"a": 1})).HasField("map_value")
+
+
+def test_reject_empty_struct() -> None:
+    with pytest.raises(ValueError):
+        _value(Struct())
+
+
+def test_integer_ranges() -> None:
+    assert _value(-(2**127)).HasField("hugeint_value")
+    assert _value(2**128 - 1).HasField("uhugeint_value")
+    for v in [-(2**127) - 1, 2**128]:
+        with pytest.raises(ValueError, match="out of the range"):
+            _value(v)
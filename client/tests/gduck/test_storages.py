@@ -0,0 +1,33 @@
+import time
+
+from gduck.client import Connection
+from gduck.proto.location_pb2 import Location
+from gduck.request import s3_object
+
+
+def _minio_object(bucket: str, key: str) -> Location:
+    return s3_object(bucket, key, endpoint="minio:9000", path_style=True, disable_ssl=True, credential="minio")
+
+
+def test_export_and_read_minio_objects(gduck_minio_connection: Connection, minio_bucket: str) -> None:
+    storage = _minio_object(minio_bucket, "round_trip/")
+    with gduck_minio_connection.transaction(":memory:", "read_write", storages=[storage]) as trans:
+        exported = trans.export(_minio_object(minio_bucket, "round_trip/data.parquet"), "SELECT i FROM range(10) t(i);")
+        assert exported.num_rows == 10
+        assert trans.query_value(f"SELECT SUM(i) FROM read_parquet('s3://{minio_bucket}/round_trip/data.parquet');") == 45
+
+
+def test_drop_secrets_of_storages_on_disconnect(gduck_minio_connection: Connection, minio_bucket: str) -> None:
+    database_file = "/tmp/gduck_test_storages.duckdb"
+    count_secrets = "SELECT COUNT(*) FROM duckdb_secrets() WHERE name LIKE 'gduck_secret_%';"
+    with gduck_minio_connection.transaction(database_file, "read_write") as other:
+        storage = _minio_object(minio_bucket, "secrets/")
+        with gduck_minio_connection.transaction(database_file, "read_write", storages=[storage]) as trans:
+            # Answered once the session is connected
+            assert trans.query_value("SELECT 1;") == 1
+            assert other.query_value(count_secrets) == 1
+        # The server drops the session after the stream is closed.
+        deadline = time.monotonic() + 10
+        while other.query_value(count_secrets) != 0:
+            assert time.monotonic() < deadline
+            time.sleep(0.1)
@@ -0,0 +1,17 @@
+from gduck.client import DuckDbTransaction
+
+
+def test_stream_rows_in_batches(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    actual = list(gduck_in_memory_rw_connection.stream_rows("SELECT i FROM range(5000) t(i);", batch_size=1000))
+    assert actual == [(i,) for i in range(5000)]
+
+def test_stream_no_rows(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    actual = list(gduck_in_memory_rw_connection.stream_rows("SELECT 1 WHERE false;"))
+    assert actual == []
+
+
+def test_stop_streaming_rows_halfway(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    for (i,) in gduck_in_memory_rw_connection.stream_rows("SELECT i FROM range(100000) t(i);", batch_size=100):
+        if i == 150:
+            break
+    assert gduck_in_memory_rw_connection.query_value("SELECT 42;") == 42
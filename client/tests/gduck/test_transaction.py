@@ -0,0 +1,16 @@
+from gduck.client import DuckDbTransaction
+
+
+def test_commit(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    gduck_in_memory_rw_connection.execute("CREATE TABLE t (i INTEGER);")
+    gduck_in_memory_rw_connection.begin()
+    gduck_in_memory_rw_connection.execute("INSERT INTO t VALUES (1);")
+    gduck_in_memory_rw_connection.commit()
+    assert gduck_in_memory_rw_connection.query_value("SELECT COUNT(*) FROM t;") == 1
+
+def test_rollback(gduck_in_memory_rw_connection: DuckDbTransaction) -> None:
+    gduck_in_memory_rw_connection.execute("CREATE TABLE t (i INTEGER);")
+    gduck_in_memory_rw_connection.begin()
+    gduck_in_memory_rw_connection.execute("INSERT INTO t VALUES (1);")
+    gduck_in_memory_rw_connection.rollback()
+    assert gduck_in_memory_rw_connection.query_value("SELECT COUNT(*) FROM t;") == 0
@@ -0,0 +1,535 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::error::{Error, Result};
+use crate::proto;
+
+const MEMORY: &str = ":memory:";
+
+/// A DuckDB database opened by the server.
+///
+/// Sessions get their own connections to it with `Database::connect`. The database is
+/// closed when the last `Arc` to it is dropped, so sessions must hold a `Lease` of it
+/// while connected.
+#[derive(Debug)]
+pub struct Database {
+    conn: Mutex<duckdb::Connection>,
+    read_only: bool,
+    // DuckDB settings the database was opened with
+    settings: std::collections::BTreeMap<String, String>,
+    leases: Mutex<Leases>,
+}
+
+// Sessions holding the database, and the timer keeping it for its TTL after the last of them.
+#[derive(Debug, Default)]
+struct Leases {
+    count: usize,
+    expiry: Option<tokio::task::JoinHandle<()>>,
+}
+
+/// A database held by a session, which keeps the database open until it is dropped.
+///
+/// A named in-memory database with a TTL is kept for the TTL after the last session
+/// holding it drops its lease, unless another session gets a lease of it in the meantime.
+#[derive(Debug)]
+pub struct Lease {
+    database: Arc<Database>,
+    ttl: Option<std::time::Duration>,
+}
+
+impl Lease {
+    fn new(database: Arc<Database>, ttl: Option<std::time::Duration>) -> Result<Lease> {
+        let mut leases = database
+            .leases
+            .lock()
+            .map_err(|_| Error::internal("database leases are poisoned"))?;
+        leases.count += 1;
+        if let Some(expiry) = leases.expiry.take() {
+            expiry.abort();
+        }
+        drop(leases);
+        Ok(Lease { database, ttl })
+    }
+}
+
+impl std::ops::Deref for Lease {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        &self.database
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let Ok(mut leases) = self.database.leases.lock() else {
+            return;
+        };
+        leases.count -= 1;
+        let Some(ttl) = self.ttl.filter(|_| leases.count == 0) else {
+            return;
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let database = self.database.clone();
+                leases.expiry = Some(runtime.spawn(async move {
+                    tokio::time::sleep(ttl).await;
+                    drop(database);
+                }));
+            }
+            Err(_) => log::warn!("Dropping a database without waiting for its TTL out of runtime"),
+        }
+    }
+}
+
+impl Database {
+    fn open(connect: proto::Connect) -> Result<Database> {
+        let read_only = connect.mode() == proto::connect::Mode::ReadOnly;
+        let settings = connect.duckdb_settings();
+        let conn = duckdb::Connection::open_with_flags(
+            PathBuf::from(&connect.file_name),
+            duckdb::Config::try_from(connect)?,
+        )
+        .map_err(|err| match Error::from(err) {
+            Error::DatabaseError { message, .. }
+                if message.contains("options were not recognized: ") =>
+            {
+                let (_, keys) = message
+                    .split_once("options were not recognized: ")
+                    .unwrap_or_default();
+                Error::InvalidRequest(format!("unknown DuckDB settings: {}", keys))
+            }
+            err => err,
+        })?;
+        Ok(Database {
+            conn: Mutex::new(conn),
+            read_only,
+            settings,
+            leases: Mutex::default(),
+        })
+    }
+
+    /// Opens a new connection to this database.
+    pub fn connect(&self) -> Result<duckdb::Connection> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| Error::internal("database connection is poisoned"))?;
+        Ok(conn.try_clone()?)
+    }
+
+    fn check_mode(&self, mode: proto::connect::Mode) -> Result<()> {
+        match (mode, self.read_only) {
+            (proto::connect::Mode::ReadOnly, false) => Err(Error::InvalidRequest(String::from(
+                "database is already opened in read-write mode",
+            ))),
+            (proto::connect::Mode::ReadWrite, true) => Err(Error::InvalidRequest(String::from(
+                "database is already opened in read-only mode",
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    // Settings take effect only when the database is opened, so the ones given by sessions
+    // sharing it must be those it was opened with, or those it runs with when it was opened
+    // without them. Settings left unset are not checked.
+    fn check_settings(&self, connect: &proto::Connect) -> Result<()> {
+        let mut unknown = Vec::new();
+        let mut different = Vec::new();
+        for (key, setting) in connect.duckdb_settings() {
+            if self.settings.get(&key) == Some(&setting) {
+                continue;
+            }
+            match (self.current_setting(&key)?, self.settings.get(&key)) {
+                (None, _) => unknown.push(key),
+                (Some(_), Some(opened)) => different.push(format!("{} = {}", key, opened)),
+                // A setting the database was opened without is the value it is running with.
+                (Some(current), None) if !current.eq_ignore_ascii_case(&setting) => {
+                    different.push(format!("{} = {}", key, current))
+                }
+                (Some(_), None) => {}
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(Error::InvalidRequest(format!(
+                "unknown DuckDB settings: {}",
+                unknown.join(", ")
+            )));
+        }
+        if different.is_empty() {
+            return Ok(());
+        }
+        Err(Error::InvalidRequest(format!(
+            "database is already opened with other DuckDB settings: {}",
+            different.join(", ")
+        )))
+    }
+
+    // Value of the setting in the database, or None if DuckDB has no such setting.
+    fn current_setting(&self, key: &str) -> Result<Option<String>> {
+        let sql = format!(
+            "SELECT current_setting({})::VARCHAR",
+            crate::uri::literal(key)
+        );
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| Error::internal("database connection is poisoned"))?;
+        match conn.query_row(&sql, [], |row| row.get::<_, Option<String>>(0)) {
+            Ok(value) => Ok(Some(value.unwrap_or_default())),
+            Err(err) => match Error::from(err) {
+                Error::DatabaseError { message, .. } if message.contains("unrecognized") => {
+                    Ok(None)
+                }
+                err => Err(err),
+            },
+        }
+    }
+}
+
+/// Databases opened by the server and shared between sessions.
+///
+/// A DuckDB file can be opened for writing by only one process at a time, so sessions
+/// on the same file share one `Database` instead of opening the file again.
+/// Named in-memory databases are shared in the same way.
+#[derive(Debug, Default)]
+pub struct Registry {
+    // Each database is opened holding the lock of its key only, so that sessions on other
+    // databases do not wait for opening it.
+    databases: Mutex<HashMap<Key, Arc<Mutex<Weak<Database>>>>>,
+    // Named in-memory databases without TTL, which live as long as the server.
+    retained: Mutex<Vec<Arc<Database>>>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Key {
+    File(PathBuf),
+    Memory(String),
+}
+
+impl Registry {
+    /// Returns a lease of the database the `Connect` message refers to, opening it if no
+    /// session uses it.
+    ///
+    /// Configuration options in `Connect` take effect only when the database is opened,
+    /// so a shared database is not returned for options other than those it was opened with.
+    pub fn open(&self, mut connect: proto::Connect) -> Result<Lease> {
+        let mut ttl = None;
+        let key = match connect.in_memory.take() {
+            Some(in_memory) => {
+                connect.file_name = String::from(MEMORY);
+                ttl = in_memory
+                    .ttl
+                    .map(std::time::Duration::try_from)
+                    .transpose()
+                    .map_err(|err| Error::InvalidRequest(format!("invalid ttl: {}", err)))?;
+                Some(in_memory.name)
+                    .filter(|name| !name.is_empty())
+                    .map(Key::Memory)
+            }
+            None => canonical_path(&connect.file_name).map(Key::File),
+        };
+        let Some(key) = key else {
+            return Lease::new(Arc::new(Database::open(connect)?), None);
+        };
+        let named = matches!(key, Key::Memory(_));
+
+        let slot = {
+            let mut databases = self
+                .databases
+                .lock()
+                .map_err(|_| Error::internal("database registry is poisoned"))?;
+            // Slots being opened are locked and kept.
+            databases.retain(|_, slot| {
+                Arc::strong_count(slot) > 1
+                    || slot
+                        .try_lock()
+                        .map_or(true, |database| database.strong_count() > 0)
+            });
+            databases.entry(key).or_default().clone()
+        };
+        let mut slot = slot
+            .lock()
+            .map_err(|_| Error::internal("database registry is poisoned"))?;
+        let database = match slot.upgrade() {
+            Some(database) => {
+                database.check_mode(connect.mode())?;
+                database.check_settings(&connect)?;
+                database
+            }
+            None => {
+                let database = Arc::new(Database::open(connect)?);
+                *slot = Arc::downgrade(&database);
+                database
+            }
+        };
+        drop(slot);
+
+        if named && ttl.is_none() {
+            let mut retained = self
+                .retained
+                .lock()
+                .map_err(|_| Error::internal("database registry is poisoned"))?;
+            if !retained.iter().any(|other| Arc::ptr_eq(other, &database)) {
+                retained.push(database.clone());
+            }
+        }
+        Lease::new(database, ttl)
+    }
+}
+
+// In-memory databases are private to a session and have no path to share.
+fn canonical_path(file_name: &str) -> Option<PathBuf> {
+    if file_name.is_empty() || file_name == MEMORY {
+        return None;
+    }
+    let path = PathBuf::from(file_name);
+    if let Ok(path) = path.canonicalize() {
+        return Some(path);
+    }
+    // The file does not exist yet and is created when it is opened.
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                std::path::Path::new(".")
+            } else {
+                parent
+            };
+            parent
+                .canonicalize()
+                .ok()
+                .map(|parent| parent.join(name))
+                .or(Some(path))
+        }
+        _ => Some(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(name: &str, threads: u32) -> proto::Connect {
+        proto::Connect {
+            in_memory: Some(proto::connect::InMemory {
+                name: name.to_owned(),
+                ttl: None,
+            }),
+            threads,
+            ..Default::default()
+        }
+    }
+
+    fn shared(lease: &Lease, other: &Lease) -> bool {
+        Arc::ptr_eq(&lease.database, &other.database)
+    }
+
+    fn count(lease: &Lease, table: &str) -> Result<i64> {
+        let sql = format!("SELECT COUNT(*) FROM {}", table);
+        Ok(lease.connect()?.query_row(&sql, [], |row| row.get(0))?)
+    }
+
+    #[test]
+    fn share_database_with_same_settings() {
+        let registry = Registry::default();
+        let database = registry.open(named("shared", 2)).unwrap();
+        assert!(shared(
+            &database,
+            &registry.open(named("shared", 2)).unwrap()
+        ));
+        // Settings left unset are those of the database.
+        assert!(shared(
+            &database,
+            &registry.open(named("shared", 0)).unwrap()
+        ));
+        assert!(!shared(
+            &database,
+            &registry.open(named("other", 2)).unwrap()
+        ));
+    }
+
+    #[test]
+    fn reject_other_settings() {
+        let registry = Registry::default();
+        let _database = registry.open(named("shared", 2)).unwrap();
+        let err = registry.open(named("shared", 3)).unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidRequest(message) if message.ends_with(": threads = 2")),
+            "{:?}",
+            err
+        );
+
+        let mut connect = named("shared", 0);
+        connect.settings.insert(
+            String::from("default_null_order"),
+            String::from("NULLS_FIRST"),
+        );
+        assert!(matches!(
+            registry.open(connect),
+            Err(Error::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn share_database_with_settings_it_runs_with() {
+        let registry = Registry::default();
+        let database = registry.open(named("shared", 0)).unwrap();
+        let threads: u32 = database
+            .connect()
+            .unwrap()
+            .query_row("SELECT current_setting('threads')", [], |row| row.get(0))
+            .unwrap();
+        assert!(shared(
+            &database,
+            &registry.open(named("shared", threads)).unwrap()
+        ));
+        let err = registry.open(named("shared", threads + 1)).unwrap_err();
+        let expected = format!(
+            "database is already opened with other DuckDB settings: threads = {}",
+            threads
+        );
+        assert!(
+            matches!(&err, Error::InvalidRequest(message) if message == &expected),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn reject_unknown_settings() {
+        let registry = Registry::default();
+        let mut connect = named("shared", 0);
+        connect
+            .settings
+            .insert(String::from("no_such_setting"), String::from("1"));
+        let err = registry.open(connect.clone()).unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidRequest(message) if message == "unknown DuckDB settings: no_such_setting"),
+            "{:?}",
+            err
+        );
+
+        // Sessions sharing a database are told the same.
+        let _database = registry.open(named("shared", 2)).unwrap();
+        let err = registry.open(connect).unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidRequest(message) if message == "unknown DuckDB settings: no_such_setting"),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn isolate_private_in_memory_databases() {
+        let registry = Registry::default();
+        let private = [
+            proto::Connect {
+                file_name: String::from(MEMORY),
+                ..Default::default()
+            },
+            proto::Connect {
+                in_memory: Some(proto::connect::InMemory::default()),
+                ..Default::default()
+            },
+        ];
+        for connect in private {
+            let database = registry.open(connect.clone()).unwrap();
+            database
+                .connect()
+                .unwrap()
+                .execute_batch("CREATE TABLE t AS SELECT 1")
+                .unwrap();
+            let other = registry.open(connect).unwrap();
+            assert!(!shared(&database, &other));
+            assert!(count(&other, "t").is_err());
+        }
+    }
+
+    #[test]
+    fn share_file_between_sessions() {
+        let path =
+            std::env::temp_dir().join(format!("gduck_share_file_{}.duckdb", std::process::id()));
+        let connect = proto::Connect {
+            file_name: path.to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        let registry = Registry::default();
+        let database = registry.open(connect.clone()).unwrap();
+        let other = registry.open(connect.clone()).unwrap();
+        assert!(shared(&database, &other));
+        database
+            .connect()
+            .unwrap()
+            .execute_batch("CREATE TABLE t AS SELECT 1")
+            .unwrap();
+        assert_eq!(count(&other, "t").unwrap(), 1);
+
+        // The file is closed with the last session and opened again by the next one.
+        let closed = Arc::downgrade(&database.database);
+        drop((database, other));
+        assert_eq!(closed.strong_count(), 0);
+        let reopened = registry
+            .open(proto::Connect {
+                threads: 2,
+                ..connect.clone()
+            })
+            .unwrap();
+        assert_eq!(count(&reopened, "t").unwrap(), 1);
+
+        // Settings of the file are those of the session opening it.
+        let err = registry
+            .open(proto::Connect {
+                threads: 3,
+                ..connect
+            })
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidRequest(message)
+                if message == "database is already opened with other DuckDB settings: threads = 2"),
+            "{:?}",
+            err
+        );
+        drop(reopened);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expire_named_database_after_last_session() {
+        let expiring = |ttl: u64| proto::Connect {
+            in_memory: Some(proto::connect::InMemory {
+                name: String::from("expiring"),
+                ttl: Some(prost_types::Duration {
+                    seconds: ttl as i64,
+                    nanos: 0,
+                }),
+            }),
+            ..Default::default()
+        };
+        let elapse = |seconds: u64| async move {
+            tokio::time::sleep(std::time::Duration::from_secs(seconds)).await;
+            tokio::task::yield_now().await;
+        };
+        let registry = Registry::default();
+        let first = registry.open(expiring(10)).unwrap();
+        let second = registry.open(expiring(10)).unwrap();
+        let database = Arc::downgrade(&first.database);
+
+        // The TTL does not run while sessions hold the database.
+        drop(first);
+        elapse(20).await;
+        assert_eq!(database.strong_count(), 1);
+
+        // It runs from when the last session is dropped, and stops when another one comes.
+        drop(second);
+        elapse(9).await;
+        let third = registry.open(expiring(10)).unwrap();
+        assert!(Arc::ptr_eq(&third.database, &database.upgrade().unwrap()));
+        elapse(20).await;
+        drop(third);
+        elapse(9).await;
+        assert_eq!(database.strong_count(), 1);
+        elapse(2).await;
+        assert_eq!(database.strong_count(), 0);
+    }
+}
@@ -1,30 +1,40 @@
 #[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("Protocol error: {message}.")]
     ProtocolError { message: String },
 
     #[error("Error occured at database: {message}.")]
-    DatabaseError { message: String },
+    DatabaseError {
+        kind: DatabaseErrorKind,
+        message: String,
+    },
 
     #[error("Query error: {message}.")]
     QueryError { message: String },
 
     #[error("Unsupported type: {t}.")]
-    UnsupportedTypeError { t: duckdb::types::Type },
+    UnsupportedTypeError { t: String },
 
-    #[error("Unsupported parquet uri: {0}.")]
-    UnsupportedParquetUri(String),
+    #[error("Unsupported uri: {0}.")]
+    UnsupportedUri(String),
 
     #[error("Invalid request: {0}.")]
     InvalidRequest(String),
 
     #[error("Internal error: {message}.")]
     InternalError { message: String },
+
+    #[error("Request was cancelled.")]
+    Cancelled,
+
+    #[error("Request exceeded its deadline.")]
+    DeadlineExceeded,
 }
 
 impl Error {
-    pub const fn unsupported_type(t: duckdb::types::Type) -> Self {
-        Error::UnsupportedTypeError { t }
+    pub fn unsupported_type<T: std::fmt::Display>(t: T) -> Self {
+        Error::UnsupportedTypeError { t: t.to_string() }
     }
 
     pub fn internal<S: AsRef<str>>(message: S) -> Self {
@@ -36,9 +46,138 @@ impl Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Category of an error raised by DuckDB.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DatabaseErrorKind {
+    Unknown,
+    Parser,
+    Binder,
+    Catalog,
+    Constraint,
+    Conversion,
+    OutOfMemory,
+    Io,
+    InvalidInput,
+    Transaction,
+    Permission,
+    Interrupted,
+    NotImplemented,
+}
+
+impl DatabaseErrorKind {
+    // DuckDB reports its exception type only as a prefix of the message like "Parser Error: ...".
+    const PREFIXES: [(&'static str, DatabaseErrorKind); 26] = [
+        ("Parser Error", DatabaseErrorKind::Parser),
+        ("Syntax Error", DatabaseErrorKind::Parser),
+        ("Binder Error", DatabaseErrorKind::Binder),
+        ("Parameter Not Resolved Error", DatabaseErrorKind::Binder),
+        ("Parameter Not Allowed Error", DatabaseErrorKind::Binder),
+        ("Catalog Error", DatabaseErrorKind::Catalog),
+        ("Dependency Error", DatabaseErrorKind::Catalog),
+        ("Constraint Error", DatabaseErrorKind::Constraint),
+        ("Conversion Error", DatabaseErrorKind::Conversion),
+        ("Out of Range Error", DatabaseErrorKind::Conversion),
+        ("Mismatch Type Error", DatabaseErrorKind::Conversion),
+        ("Invalid type Error", DatabaseErrorKind::Conversion),
+        ("Decimal Error", DatabaseErrorKind::Conversion),
+        ("Divide by Zero Error", DatabaseErrorKind::Conversion),
+        ("Out of Memory Error", DatabaseErrorKind::OutOfMemory),
+        ("IO Error", DatabaseErrorKind::Io),
+        ("HTTP Error", DatabaseErrorKind::Io),
+        ("Invalid Input Error", DatabaseErrorKind::InvalidInput),
+        (
+            "Invalid Configuration Error",
+            DatabaseErrorKind::InvalidInput,
+        ),
+        ("Settings Error", DatabaseErrorKind::InvalidInput),
+        ("TransactionContext Error", DatabaseErrorKind::Transaction),
+        ("Permission Error", DatabaseErrorKind::Permission),
+        ("INTERRUPT Error", DatabaseErrorKind::Interrupted),
+        ("Not implemented Error", DatabaseErrorKind::NotImplemented),
+        ("Missing Extension Error", DatabaseErrorKind::NotImplemented),
+        (
+            "Extension Autoloading Error",
+            DatabaseErrorKind::NotImplemented,
+        ),
+    ];
+
+    pub fn from_message<S: AsRef<str>>(message: S) -> Self {
+        let message = message.as_ref();
+        Self::PREFIXES
+            .iter()
+            .find(|(prefix, _)| message.starts_with(prefix))
+            .map(|(_, kind)| *kind)
+            .unwrap_or(DatabaseErrorKind::Unknown)
+    }
+}
+
+impl Error {
+    pub fn status_code(&self) -> tonic::Code {
+        match self {
+            Error::ProtocolError { .. } => tonic::Code::FailedPrecondition,
+            Error::DatabaseError { kind, .. } => match kind {
+                DatabaseErrorKind::Parser
+                | DatabaseErrorKind::Binder
+                | DatabaseErrorKind::Conversion
+                | DatabaseErrorKind::InvalidInput => tonic::Code::InvalidArgument,
+                DatabaseErrorKind::Catalog => tonic::Code::NotFound,
+                DatabaseErrorKind::Constraint => tonic::Code::FailedPrecondition,
+                DatabaseErrorKind::OutOfMemory => tonic::Code::ResourceExhausted,
+                DatabaseErrorKind::Io => tonic::Code::Unavailable,
+                DatabaseErrorKind::Transaction => tonic::Code::Aborted,
+                DatabaseErrorKind::Permission => tonic::Code::PermissionDenied,
+                DatabaseErrorKind::Interrupted => tonic::Code::Cancelled,
+                DatabaseErrorKind::NotImplemented => tonic::Code::Unimplemented,
+                DatabaseErrorKind::Unknown => tonic::Code::Internal,
+            },
+            Error::QueryError { .. } => tonic::Code::InvalidArgument,
+            Error::UnsupportedTypeError { .. } => tonic::Code::Unimplemented,
+            Error::UnsupportedUri(_) => tonic::Code::InvalidArgument,
+            Error::InvalidRequest(_) => tonic::Code::InvalidArgument,
+            Error::InternalError { .. } => tonic::Code::Internal,
+            Error::Cancelled => tonic::Code::Cancelled,
+            Error::DeadlineExceeded => tonic::Code::DeadlineExceeded,
+        }
+    }
+}
+
+impl From<Error> for tonic::Status {
+    fn from(value: Error) -> Self {
+        tonic::Status::new(value.status_code(), value.to_string())
+    }
+}
+
 impl From<duckdb::Error> for Error {
     fn from(value: duckdb::Error) -> Self {
-        Self::DatabaseError {
+        match value {
+            duckdb::Error::FromSqlConversionFailure(_, _, err)
+            | duckdb::Error::ToSqlConversionFailure(err) => match err.downcast::<Error>() {
+                Ok(err) => *err,
+                Err(err) => Self::DatabaseError {
+                    kind: DatabaseErrorKind::Conversion,
+                    message: err.to_string(),
+                },
+            },
+            duckdb::Error::InvalidParameterCount(..) | duckdb::Error::InvalidParameterName(_) => {
+                Self::DatabaseError {
+                    kind: DatabaseErrorKind::InvalidInput,
+                    message: value.to_string(),
+                }
+            }
+            _ => {
+                let message = value.to_string();
+                Self::DatabaseError {
+                    kind: DatabaseErrorKind::from_message(&message),
+                    message,
+                }
+            }
+        }
+    }
+}
+
+impl From<duckdb::arrow::error::ArrowError> for Error {
+    fn from(value: duckdb::arrow::error::ArrowError) -> Self {
+        Self::InternalError {
             message: value.to_string(),
         }
     }
@@ -48,17 +187,128 @@ impl From<duckdb::types::FromSqlError> for Error {
     fn from(value: duckdb::types::FromSqlError) -> Self {
         match value {
             duckdb::types::FromSqlError::InvalidType => Error::DatabaseError {
+                kind: DatabaseErrorKind::Conversion,
                 message: String::from("invalid type"),
             },
             duckdb::types::FromSqlError::OutOfRange(range) => Error::DatabaseError {
+                kind: DatabaseErrorKind::Conversion,
                 message: format!("value out of range ({})", range),
             },
-            duckdb::types::FromSqlError::Other(err) => Error::DatabaseError {
-                message: err.to_string(),
+            duckdb::types::FromSqlError::Other(err) => match err.downcast::<Error>() {
+                Ok(err) => *err,
+                Err(err) => Error::DatabaseError {
+                    kind: DatabaseErrorKind::Conversion,
+                    message: err.to_string(),
+                },
             },
             _ => Error::DatabaseError {
+                kind: DatabaseErrorKind::Unknown,
                 message: String::from("unknown"),
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind_from_message() {
+        for (message, kind) in [
+            (
+                "Parser Error: syntax error at or near \"SELEC\"",
+                DatabaseErrorKind::Parser,
+            ),
+            (
+                "Catalog Error: Table with name t does not exist!",
+                DatabaseErrorKind::Catalog,
+            ),
+            (
+                "Conversion Error: Could not convert string 'a' to INT32",
+                DatabaseErrorKind::Conversion,
+            ),
+            (
+                "TransactionContext Error: cannot start a transaction within a transaction",
+                DatabaseErrorKind::Transaction,
+            ),
+            (
+                "Invalid Configuration Error: ...",
+                DatabaseErrorKind::InvalidInput,
+            ),
+            ("Something went wrong", DatabaseErrorKind::Unknown),
+            // The prefix is matched only at the start.
+            ("Error: Parser Error: ...", DatabaseErrorKind::Unknown),
+        ] {
+            assert_eq!(
+                DatabaseErrorKind::from_message(message),
+                kind,
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn kind_of_duckdb_errors() {
+        let conn = duckdb::Connection::open_in_memory().unwrap();
+        for (sql, kind) in [
+            ("SELEC 1", DatabaseErrorKind::Parser),
+            ("SELECT * FROM missing", DatabaseErrorKind::Catalog),
+            ("SELECT 'a'::INTEGER", DatabaseErrorKind::Conversion),
+            ("SELECT unknown_column", DatabaseErrorKind::Binder),
+            ("COMMIT", DatabaseErrorKind::Transaction),
+        ] {
+            let err = Error::from(conn.execute_batch(sql).unwrap_err());
+            assert!(
+                matches!(err, Error::DatabaseError { kind: actual, .. } if actual == kind),
+                "{}: {:?}",
+                sql,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn status_code() {
+        let database = |kind| Error::DatabaseError {
+            kind,
+            message: String::new(),
+        };
+        for (err, code) in [
+            (
+                database(DatabaseErrorKind::Parser),
+                tonic::Code::InvalidArgument,
+            ),
+            (database(DatabaseErrorKind::Catalog), tonic::Code::NotFound),
+            (
+                database(DatabaseErrorKind::Constraint),
+                tonic::Code::FailedPrecondition,
+            ),
+            (
+                database(DatabaseErrorKind::Transaction),
+                tonic::Code::Aborted,
+            ),
+            (database(DatabaseErrorKind::Unknown), tonic::Code::Internal),
+            (
+                Error::ProtocolError {
+                    message: String::new(),
+                },
+                tonic::Code::FailedPrecondition,
+            ),
+            (
+                Error::InvalidRequest(String::new()),
+                tonic::Code::InvalidArgument,
+            ),
+            (
+                Error::unsupported_type("VARINT"),
+                tonic::Code::Unimplemented,
+            ),
+            (Error::Cancelled, tonic::Code::Cancelled),
+            (Error::DeadlineExceeded, tonic::Code::DeadlineExceeded),
+        ] {
+            assert_eq!(err.status_code(), code, "{:?}", err);
+            assert_eq!(tonic::Status::from(err).code(), code);
+        }
+    }
+}
@@ -1,37 +1,1035 @@
+use crate::database::Lease;
 use crate::error::{Error, Result};
 use crate::proto;
 use crate::uri::Uri;
 
+/// Number of rows in a RowBatch when a client does not specify it.
+pub const DEFAULT_BATCH_SIZE: usize = 1024;
+
+/// Largest number of bytes of Arrow IPC stream in a response, well under the 4 MiB a gRPC
+/// message can hold by default.
+const ARROW_IPC_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Largest number of rows DuckDB's appender takes in a record batch, which is its vector size.
+const APPEND_BATCH_SIZE: usize = 2048;
+
+/// Params of a statement, which are given by position or by the names of `$name` placeholders.
+pub enum StatementParams {
+    Positional(Vec<proto::scalar_value::Kind>),
+    Named(Vec<(String, proto::scalar_value::Kind)>),
+}
+
+impl StatementParams {
+    /// Values of the params in the positions of the placeholders, where `names` are those of
+    /// the `$name` placeholders numbered by `number_placeholders`.
+    pub fn positional(self, names: &[String]) -> Result<Vec<proto::scalar_value::Kind>> {
+        match self {
+            StatementParams::Positional(params) => Ok(params),
+            StatementParams::Named(mut params) => {
+                if let Some((name, _)) = params.iter().find(|(name, _)| !names.contains(name)) {
+                    return Err(Error::InvalidRequest(format!(
+                        "the statement has no param ${}",
+                        name
+                    )));
+                }
+                names
+                    .iter()
+                    .map(|name| {
+                        let i = params
+                            .iter()
+                            .position(|(given, _)| given == name)
+                            .ok_or_else(|| {
+                                Error::InvalidRequest(format!("param ${} is not given", name))
+                            })?;
+                        Ok(params.swap_remove(i).1)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// The SQL with its `$name` placeholders replaced with `$1`, `$2`, ... in the order the names
+/// first appear, and the names in that order.
+///
+/// duckdb-rs binds params only by position and does not tell the positions DuckDB gives to
+/// named placeholders, so they are numbered here.
+pub fn number_placeholders(sql: &str) -> (std::borrow::Cow<'_, str>, Vec<String>) {
+    let mut names: Vec<String> = Vec::new();
+    let numbered = replace_placeholders(sql, |placeholder| match placeholder {
+        Placeholder::Named(name) => {
+            let position = match names.iter().position(|given| given == name) {
+                Some(position) => position,
+                None => {
+                    names.push(name.to_owned());
+                    names.len() - 1
+                }
+            };
+            Ok(Some(format!("${}", position + 1)))
+        }
+        _ => Ok(None),
+    })
+    .unwrap_or_default();
+    match numbered {
+        Some(numbered) => (std::borrow::Cow::Owned(numbered), names),
+        None => (std::borrow::Cow::Borrowed(sql), names),
+    }
+}
+
+/// The SQL with the placeholders of nested params replaced with expressions building them
+/// from their leaves, and the params of the placeholders in the SQL.
+///
+/// duckdb-rs cannot bind LIST, STRUCT, MAP or ARRAY values, so each of their leaves is bound
+/// to a placeholder of its own, e.g. `$1` given `[1, 2]` becomes `list_value($1, $2)`.
+/// The params are numbered again in their order, with a number for each leaf.
+pub fn expand_nested_params(
+    sql: &str,
+    params: Vec<proto::scalar_value::Kind>,
+) -> Result<(std::borrow::Cow<'_, str>, Vec<proto::scalar_value::Kind>)> {
+    fn expression(
+        kind: proto::scalar_value::Kind,
+        leaves: &mut Vec<proto::scalar_value::Kind>,
+    ) -> Result<String> {
+        fn element(
+            value: Option<proto::ScalarValue>,
+            leaves: &mut Vec<proto::scalar_value::Kind>,
+        ) -> Result<String> {
+            match value.and_then(|value| value.kind) {
+                Some(kind) => expression(kind, leaves),
+                None => Err(Error::InvalidRequest(String::from(
+                    "value of nested param is missing",
+                ))),
+            }
+        }
+
+        fn elements(
+            values: Vec<proto::ScalarValue>,
+            leaves: &mut Vec<proto::scalar_value::Kind>,
+        ) -> Result<String> {
+            Ok(values
+                .into_iter()
+                .map(|value| element(Some(value), leaves))
+                .collect::<Result<Vec<String>>>()?
+                .join(", "))
+        }
+
+        match kind {
+            proto::scalar_value::Kind::ListValue(list) => {
+                Ok(format!("list_value({})", elements(list.values, leaves)?))
+            }
+            proto::scalar_value::Kind::ArrayValue(array) => {
+                Ok(format!("array_value({})", elements(array.values, leaves)?))
+            }
+            proto::scalar_value::Kind::StructValue(value) if value.fields.is_empty() => Err(
+                Error::InvalidRequest(String::from("STRUCT param must have fields")),
+            ),
+            proto::scalar_value::Kind::StructValue(value) => Ok(format!(
+                "struct_pack({})",
+                value
+                    .fields
+                    .into_iter()
+                    .map(|field| Ok(format!(
+                        "\"{}\" := {}",
+                        field.name.replace('"', "\"\""),
+                        element(field.value, leaves)?
+                    )))
+                    .collect::<Result<Vec<String>>>()?
+                    .join(", ")
+            )),
+            proto::scalar_value::Kind::MapValue(map) => {
+                let (keys, values): (Vec<_>, Vec<_>) = map
+                    .entries
+                    .into_iter()
+                    .map(|entry| (entry.key, entry.value))
+                    .unzip();
+                let keys = keys
+                    .into_iter()
+                    .map(|key| element(key, leaves))
+                    .collect::<Result<Vec<String>>>()?;
+                let values = values
+                    .into_iter()
+                    .map(|value| element(value, leaves))
+                    .collect::<Result<Vec<String>>>()?;
+                Ok(format!(
+                    "map(list_value({}), list_value({}))",
+                    keys.join(", "),
+                    values.join(", ")
+                ))
+            }
+            proto::scalar_value::Kind::UnionValue(union) => {
+                element(union.value.map(|v| *v), leaves)
+            }
+            kind => {
+                leaves.push(kind);
+                Ok(format!("${}", leaves.len()))
+            }
+        }
+    }
+
+    let nested = params.iter().any(|param| {
+        matches!(
+            param,
+            proto::scalar_value::Kind::ListValue(_)
+                | proto::scalar_value::Kind::ArrayValue(_)
+                | proto::scalar_value::Kind::StructValue(_)
+                | proto::scalar_value::Kind::MapValue(_)
+                | proto::scalar_value::Kind::UnionValue(_)
+        )
+    });
+    if !nested {
+        return Ok((std::borrow::Cow::Borrowed(sql), params));
+    }
+
+    let mut leaves = Vec::new();
+    let expressions = params
+        .into_iter()
+        .map(|param| expression(param, &mut leaves))
+        .collect::<Result<Vec<String>>>()?;
+    let mut next = 0;
+    let expanded = replace_placeholders(sql, |placeholder| {
+        let number = match placeholder {
+            Placeholder::Numbered(number) => number,
+            Placeholder::Next => {
+                next += 1;
+                next
+            }
+            Placeholder::Named(name) => {
+                return Err(Error::internal(format!("param ${} is not numbered", name)))
+            }
+        };
+        match number.checked_sub(1).and_then(|i| expressions.get(i)) {
+            Some(expression) => Ok(Some(expression.clone())),
+            None => Err(Error::InvalidRequest(format!(
+                "param ${} is not given",
+                number
+            ))),
+        }
+    })?;
+    Ok((
+        expanded.map_or(std::borrow::Cow::Borrowed(sql), std::borrow::Cow::Owned),
+        leaves,
+    ))
+}
+
+/// Placeholder of a param in SQL.
+enum Placeholder<'s> {
+    // `$name`
+    Named(&'s str),
+    // `$1`, `$2`, ...
+    Numbered(usize),
+    // `?`, which is numbered after the former one
+    Next,
+}
+
+/// Token of SQL the server looks for.
+enum Token<'s> {
+    Placeholder(Placeholder<'s>),
+    // `;` ending a statement
+    Semicolon,
+}
+
+/// The SQL with its placeholders replaced with the text `replace` returns for each of them,
+/// or None if it returns None for all of them.
+fn replace_placeholders<F>(sql: &str, mut replace: F) -> Result<Option<String>>
+where
+    F: FnMut(Placeholder<'_>) -> Result<Option<String>>,
+{
+    let mut replaced = String::new();
+    let mut copied = 0;
+    for (range, token) in tokens(sql) {
+        let Token::Placeholder(placeholder) = token else {
+            continue;
+        };
+        if let Some(text) = replace(placeholder)? {
+            replaced.push_str(&sql[copied..range.start]);
+            replaced.push_str(&text);
+            copied = range.end;
+        }
+    }
+
+    if copied == 0 {
+        return Ok(None);
+    }
+    replaced.push_str(&sql[copied..]);
+    Ok(Some(replaced))
+}
+
+/// Statements of the SQL separated by `;`, leaving out empty ones.
+fn split_statements(sql: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    for (range, token) in tokens(sql) {
+        if let Token::Semicolon = token {
+            statements.push(&sql[start..range.start]);
+            start = range.end;
+        }
+    }
+    statements.push(&sql[start..]);
+    statements.retain(|statement| !skip_comments(statement).is_empty());
+    statements
+}
+
+/// Tokens of the SQL and their ranges. String literals, quoted identifiers and comments
+/// are skipped.
+fn tokens(sql: &str) -> Vec<(std::ops::Range<usize>, Token<'_>)> {
+    fn is_identifier(byte: u8) -> bool {
+        byte.is_ascii_alphanumeric() || byte == b'_' || byte >= 0x80
+    }
+
+    // Position after the literal or identifier quoted with the quote at `start`.
+    fn after_quoted(bytes: &[u8], start: usize, backslash_escapes: bool) -> usize {
+        let quote = bytes[start];
+        let mut i = start + 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' if backslash_escapes => i += 1,
+                byte if byte == quote && bytes.get(i + 1) == Some(&quote) => i += 1,
+                byte if byte == quote => return i + 1,
+                _ => {}
+            }
+            i += 1;
+        }
+        bytes.len()
+    }
+
+    // Position after the block comment at `start`, which may be nested.
+    fn after_comment(bytes: &[u8], start: usize) -> usize {
+        let mut depth = 0;
+        let mut i = start;
+        while i + 1 < bytes.len() {
+            match &bytes[i..i + 2] {
+                b"/*" => (depth, i) = (depth + 1, i + 2),
+                b"*/" if depth == 1 => return i + 2,
+                b"*/" => (depth, i) = (depth - 1, i + 2),
+                _ => i += 1,
+            }
+        }
+        bytes.len()
+    }
+
+    let bytes = sql.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let follows_identifier = i > 0 && (is_identifier(bytes[i - 1]) || bytes[i - 1] == b'$');
+        let (token, end) = match bytes[i] {
+            b'\'' => {
+                let escaped = follows_identifier
+                    && matches!(bytes[i - 1], b'E' | b'e')
+                    && (i < 2 || !is_identifier(bytes[i - 2]));
+                (None, after_quoted(bytes, i, escaped))
+            }
+            b'"' => (None, after_quoted(bytes, i, false)),
+            b'-' if bytes.get(i + 1) == Some(&b'-') => (
+                None,
+                bytes[i..]
+                    .iter()
+                    .position(|byte| *byte == b'\n')
+                    .map_or(bytes.len(), |end| i + end),
+            ),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => (None, after_comment(bytes, i)),
+            b'?' => (Some(Token::Placeholder(Placeholder::Next)), i + 1),
+            b';' => (Some(Token::Semicolon), i + 1),
+            b'$' if !follows_identifier => {
+                let start = i + 1;
+                let end = start
+                    + bytes[start..]
+                        .iter()
+                        .take_while(|byte| is_identifier(**byte))
+                        .count();
+                let tag = &sql[start..end];
+                let numbered = tag.starts_with(|c: char| c.is_ascii_digit());
+                if bytes.get(end) == Some(&b'$') && !numbered {
+                    // Dollar quoted string like $tag$...$tag$
+                    let delimiter = &sql[i..=end];
+                    let end = sql[end + 1..]
+                        .find(delimiter)
+                        .map_or(bytes.len(), |at| end + 1 + at + delimiter.len());
+                    (None, end)
+                } else if numbered {
+                    let number = tag.parse().ok().map(Placeholder::Numbered);
+                    (number.map(Token::Placeholder), end)
+                } else if !tag.is_empty() {
+                    (Some(Token::Placeholder(Placeholder::Named(tag))), end)
+                } else {
+                    (None, end)
+                }
+            }
+            _ => (None, i + 1),
+        };
+        if let Some(token) = token {
+            tokens.push((i..end, token));
+        }
+        i = end;
+    }
+    tokens
+}
+
+// The SQL after the whitespace and comments it begins with.
+fn skip_comments(sql: &str) -> &str {
+    let mut rest = sql;
+    loop {
+        rest = rest.trim_start();
+        if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment.split_once('\n').map_or("", |(_, rest)| rest);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, rest)| rest);
+        } else {
+            return rest;
+        }
+    }
+}
+
+// Whether the statement opens or closes a transaction, told by its first keyword.
+fn transaction_change(sql: &str) -> Option<bool> {
+    let keyword = skip_comments(sql)
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or_default();
+    match keyword.to_ascii_uppercase().as_str() {
+        "BEGIN" | "START" => Some(true),
+        "COMMIT" | "END" | "ROLLBACK" | "ABORT" => Some(false),
+        _ => None,
+    }
+}
+
+// Quoted identifier of the name
+fn identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// Secrets belong to the database, which sessions may share, so each is named after the
+// session creating it.
+fn secret_name(session: u64) -> String {
+    static LAST_SECRET: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+    format!(
+        "gduck_secret_{}_{}",
+        session,
+        LAST_SECRET.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1
+    )
+}
+
+/// DuckDB secret to access a URI, which is dropped when this is dropped.
+struct Secret<'g> {
+    gduck: &'g Gduck,
+    name: String,
+}
+
+impl Drop for Secret<'_> {
+    fn drop(&mut self) {
+        let drop_secret = format!("DROP TEMPORARY SECRET IF EXISTS {}", self.name);
+        if let Err(err) = self.gduck.conn.execute_batch(&drop_secret) {
+            log::warn!("Failed to drop secret {}: {}", self.name, err);
+        }
+    }
+}
+
+/// Rows being appended to a table, which are flushed by `finish`.
+///
+/// Unless a transaction is open, they are appended in a transaction of their own which is
+/// committed by `finish` and rolled back if the `Append` is dropped before.
+pub struct Append<'g> {
+    gduck: &'g Gduck,
+    // Taken when this is dropped to flush the rows before the transaction is rolled back
+    appender: Option<duckdb::Appender<'g>>,
+    // Quoted name of the table, into which rows with nested values are inserted
+    table: String,
+    num_rows: u64,
+    implicit: bool,
+}
+
+impl Drop for Append<'_> {
+    fn drop(&mut self) {
+        drop(self.appender.take());
+        if self.implicit {
+            if let Err(err) = self.gduck.rollback() {
+                log::warn!("Failed to roll back the rows appended: {}", err);
+            }
+        }
+    }
+}
+
+impl Append<'_> {
+    pub fn append_rows(&mut self, rows: proto::AppendRows) -> Result<()> {
+        self.gduck.check_interrupt()?;
+        let appender = self
+            .appender
+            .as_mut()
+            .ok_or_else(|| Error::internal("appender has been closed"))?;
+        match rows.kind {
+            Some(proto::append_rows::Kind::Rows(batch)) => {
+                for row in batch.rows {
+                    let values = row
+                        .values
+                        .into_iter()
+                        .map(|value| match value.kind {
+                            Some(kind) => Ok(kind),
+                            None => Err(Error::InvalidRequest(String::from(
+                                "value of appended row is missing",
+                            ))),
+                        })
+                        .collect::<Result<Vec<proto::scalar_value::Kind>>>()?;
+                    if values.iter().any(is_nested) {
+                        // duckdb-rs appends only primitive values, so a row with nested ones
+                        // is inserted with their leaves bound as params, after the rows
+                        // appended before it.
+                        appender.flush()?;
+                        let placeholders = (1..=values.len())
+                            .map(|i| format!("${}", i))
+                            .collect::<Vec<String>>()
+                            .join(", ");
+                        let sql = format!("INSERT INTO {} VALUES ({})", self.table, placeholders);
+                        let (sql, params) = expand_nested_params(&sql, values)?;
+                        self.gduck
+                            .statement(&sql)?
+                            .execute(duckdb::params_from_iter(params))?;
+                    } else {
+                        appender.append_row(duckdb::appender_params_from_iter(values))?;
+                    }
+                    self.num_rows += 1;
+                }
+                Ok(())
+            }
+            Some(proto::append_rows::Kind::ArrowIpc(bytes)) => {
+                let batches =
+                    arrow_ipc::reader::StreamReader::try_new(std::io::Cursor::new(bytes), None)?;
+                for batch in batches {
+                    let batch = decode_dictionaries(batch?)?;
+                    // The appender takes up to a vector of DuckDB at once.
+                    for offset in (0..batch.num_rows()).step_by(APPEND_BATCH_SIZE) {
+                        self.gduck.check_interrupt()?;
+                        let length = APPEND_BATCH_SIZE.min(batch.num_rows() - offset);
+                        appender.append_record_batch(batch.slice(offset, length))?;
+                        self.num_rows += length as u64;
+                    }
+                }
+                Ok(())
+            }
+            None => Err(Error::InvalidRequest(String::from(
+                "rows to append are missing",
+            ))),
+        }
+    }
+
+    pub fn finish(mut self) -> Result<proto::response::QueryResult> {
+        if let Some(appender) = self.appender.as_mut() {
+            appender.flush()?;
+        }
+        if self.implicit {
+            self.implicit = false;
+            self.gduck.commit()?;
+        }
+        Ok(proto::response::QueryResult {
+            kind: Some(proto::response::query_result::Kind::Appended(
+                proto::Appended {
+                    num_rows: self.num_rows,
+                },
+            )),
+            ..Default::default()
+        })
+    }
+}
+
+// Whether the value is a LIST, ARRAY, STRUCT or MAP, or a UNION of one.
+fn is_nested(kind: &proto::scalar_value::Kind) -> bool {
+    match kind {
+        proto::scalar_value::Kind::ListValue(_)
+        | proto::scalar_value::Kind::ArrayValue(_)
+        | proto::scalar_value::Kind::StructValue(_)
+        | proto::scalar_value::Kind::MapValue(_) => true,
+        proto::scalar_value::Kind::UnionValue(union) => union
+            .value
+            .as_ref()
+            .and_then(|value| value.kind.as_ref())
+            .is_some_and(is_nested),
+        _ => false,
+    }
+}
+
+// duckdb-rs does not append dictionary arrays, so they are appended as their values.
+fn decode_dictionaries(
+    batch: duckdb::arrow::record_batch::RecordBatch,
+) -> Result<duckdb::arrow::record_batch::RecordBatch> {
+    use duckdb::arrow::datatypes::DataType;
+
+    let schema = batch.schema();
+    if !schema
+        .fields()
+        .iter()
+        .any(|field| matches!(field.data_type(), DataType::Dictionary(..)))
+    {
+        return Ok(batch);
+    }
+    let (fields, columns) = schema
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .map(|(field, column)| match field.data_type() {
+            DataType::Dictionary(_, value_type) => Ok((
+                field
+                    .as_ref()
+                    .clone()
+                    .with_data_type(value_type.as_ref().clone()),
+                duckdb::arrow::compute::cast(column, value_type)?,
+            )),
+            _ => Ok((field.as_ref().clone(), column.clone())),
+        })
+        .collect::<Result<(Vec<_>, Vec<_>)>>()?;
+    Ok(duckdb::arrow::record_batch::RecordBatch::try_new(
+        std::sync::Arc::new(duckdb::arrow::datatypes::Schema::new_with_metadata(
+            fields,
+            schema.metadata().clone(),
+        )),
+        columns,
+    )?)
+}
+
+/// Statement prepared by a client, which is kept until this is dropped.
+pub struct Prepared<'g> {
+    gduck: &'g Gduck,
+    statement: duckdb::Statement<'g>,
+    // SQL of the statement with its `$name` placeholders numbered
+    sql: String,
+    // Names of the `$name` placeholders in the order of their numbers
+    names: Vec<String>,
+    schema: Option<proto::Schema>,
+    // Whether running the statement opens or closes a transaction
+    transaction: Option<bool>,
+}
+
+impl Prepared<'_> {
+    /// Result telling the client the handle the statement is given.
+    pub fn prepared(&self, handle: u64) -> proto::response::QueryResult {
+        proto::response::QueryResult {
+            kind: Some(proto::response::query_result::Kind::Prepared(
+                proto::Prepared {
+                    handle,
+                    parameter_count: self.statement.parameter_count() as u32,
+                    schema: self.schema.clone(),
+                },
+            )),
+            ..Default::default()
+        }
+    }
+
+    /// Runs the statement and passes its result in the requested form to `sink`.
+    pub fn run<F: FnMut(proto::response::QueryResult) -> Result<()>>(
+        &mut self,
+        query: proto::query::ExecutePrepared,
+        mut sink: F,
+    ) -> Result<()> {
+        let output = query.output();
+        let params = StatementParams::try_from(query.params.unwrap_or_default())?;
+        self.gduck.check_interrupt()?;
+        let params = params.positional(&self.names)?;
+        let gduck = self.gduck;
+        if let Some(open) = self.transaction {
+            gduck.transaction.set(open);
+        }
+        // Nested params are bound to a statement of their own shape, which is taken from
+        // the cache instead.
+        let (sql, params) = expand_nested_params(&self.sql, params)?;
+        let mut expanded;
+        let statement: &mut duckdb::Statement<'_> = match sql {
+            std::borrow::Cow::Borrowed(_) => &mut self.statement,
+            std::borrow::Cow::Owned(sql) => {
+                expanded = gduck.statement(&sql)?;
+                &mut expanded
+            }
+        };
+        match output {
+            proto::query::execute_prepared::Output::Execute => {
+                sink(gduck.execute_statement(statement, params)?)
+            }
+            proto::query::execute_prepared::Output::Value => {
+                sink(gduck.query_statement_value(statement, params)?)
+            }
+            proto::query::execute_prepared::Output::Rows => {
+                sink(gduck.query_statement_rows(statement, params)?)
+            }
+            proto::query::execute_prepared::Output::StreamRows => {
+                gduck.stream_statement_rows(statement, params, query.batch_size as usize, sink)
+            }
+            proto::query::execute_prepared::Output::Arrow => {
+                gduck.query_statement_arrow(statement, params, sink)
+            }
+        }
+    }
+}
+
+/// Stops the request a `Gduck` is running from other threads.
+#[derive(Clone)]
+pub struct Canceller {
+    cancelled: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    interrupt: std::sync::Arc<duckdb::InterruptHandle>,
+}
+
+impl Canceller {
+    /// Cancels the request, interrupting the statement DuckDB is running for it.
+    pub fn cancel(&self) {
+        self.cancelled
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        self.interrupt.interrupt();
+    }
+
+    /// Interrupts the running statement, which fails with `Error::DeadlineExceeded`
+    /// once the deadline of the request has passed.
+    pub fn interrupt(&self) {
+        self.interrupt.interrupt();
+    }
+}
+
 pub struct Gduck {
+    // Unique in the process
+    id: u64,
     conn: duckdb::Connection,
+    // Declared after `conn` so that the connection is closed before the database.
+    database: Option<Lease>,
+    cancelled: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    deadline: std::cell::Cell<Option<std::time::Instant>>,
+    // Whether a transaction is open, which duckdb-rs does not tell
+    transaction: std::cell::Cell<bool>,
+    // Names of the secrets to access the storages given at connecting
+    storages: Vec<String>,
+}
+
+impl Drop for Gduck {
+    fn drop(&mut self) {
+        for name in &self.storages {
+            let drop_secret = format!("DROP TEMPORARY SECRET IF EXISTS {}", name);
+            if let Err(err) = self.conn.execute_batch(&drop_secret) {
+                log::warn!("Failed to drop secret {}: {}", name, err);
+            }
+        }
+    }
 }
 
 impl Gduck {
     pub fn new(conn: duckdb::Connection) -> Self {
-        Self { conn }
+        static LAST_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+        Self {
+            id: LAST_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1,
+            conn,
+            database: None,
+            cancelled: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            deadline: std::cell::Cell::new(None),
+            transaction: std::cell::Cell::new(false),
+            storages: Vec::new(),
+        }
     }
 
-    pub fn connect(conn: proto::Connect) -> Result<Gduck> {
-        let conn = duckdb::Connection::open_with_flags(
-            std::path::PathBuf::from(&conn.file_name),
-            duckdb::Config::try_from(conn)?,
-        )
-        .map_err(crate::error::Error::from)?;
-        Ok(Gduck::new(conn))
+    /// Returns a handle to stop the running request from other threads.
+    pub fn canceller(&self) -> Canceller {
+        Canceller {
+            cancelled: self.cancelled.clone(),
+            interrupt: self.conn.interrupt_handle(),
+        }
+    }
+
+    /// Prepares for the next request: clears a former cancellation and sets its deadline.
+    pub fn start_request(&self, deadline: Option<std::time::Instant>) {
+        self.cancelled
+            .store(false, std::sync::atomic::Ordering::SeqCst);
+        self.deadline.set(deadline);
     }
 
+    // DuckDB clears an interruption when a statement starts, so one coming just before that
+    // is caught here: before a statement runs and between fetched rows.
+    fn check_interrupt(&self) -> Result<()> {
+        if self.cancelled.load(std::sync::atomic::Ordering::SeqCst) {
+            Err(Error::Cancelled)
+        } else if self
+            .deadline
+            .get()
+            .is_some_and(|deadline| deadline <= std::time::Instant::now())
+        {
+            Err(Error::DeadlineExceeded)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reports a statement DuckDB interrupted as the request being cancelled or exceeding
+    /// its deadline, which are the reasons it is interrupted for.
+    pub fn interruption(&self, err: Error) -> Error {
+        match err {
+            Error::DatabaseError {
+                kind: crate::error::DatabaseErrorKind::Interrupted,
+                ..
+            } => self.check_interrupt().err().unwrap_or(err),
+            err => err,
+        }
+    }
+
+    /// Opens a connection to the database, which is kept open while the `Gduck` lives.
+    /// Queries can access the storages by their URIs as long as the connection is open.
+    pub fn connect(database: Lease, storages: Vec<proto::Location>) -> Result<Gduck> {
+        let mut gduck = Gduck::new(database.connect()?);
+        gduck.database = Some(database);
+        for location in storages {
+            let name = secret_name(gduck.id);
+            if let Some(create_secret) = Uri::try_from(location)?.create_secret(&name)? {
+                gduck.conn.execute_batch(&create_secret)?;
+                gduck.storages.push(name);
+            }
+        }
+        Ok(gduck)
+    }
+
+    // Statements are taken from the cache of the connection so that the ones run repeatedly
+    // are not parsed and planned again.
+    //
+    // Transaction statements change the state of the transaction when they are taken, as BEGIN
+    // fails only in an open transaction while COMMIT and ROLLBACK close it even if they fail.
+    fn statement(&self, sql: &str) -> Result<duckdb::CachedStatement<'_>> {
+        let sql = self.last_statement(sql)?;
+        let statement = self.conn.prepare_cached(sql)?;
+        if let Some(open) = transaction_change(sql) {
+            self.transaction.set(open);
+        }
+        Ok(statement)
+    }
+
+    // Runs the statements of the SQL before its last one and returns the last one.
+    //
+    // duckdb-rs runs them when it prepares the SQL but not when it takes the statement from
+    // its cache, so they are run here each time, keeping track of the transaction as they go.
+    fn last_statement<'s>(&self, sql: &'s str) -> Result<&'s str> {
+        let statements = split_statements(sql);
+        let Some((last, former)) = statements.split_last() else {
+            return Ok(sql);
+        };
+        for statement in former {
+            self.check_interrupt()?;
+            if let Some(open) = transaction_change(statement) {
+                self.transaction.set(open);
+            }
+            self.conn.execute_batch(statement)?;
+        }
+        Ok(last)
+    }
+
+    // Statement of the SQL with its `$name` placeholders numbered and nested params expanded,
+    // and the params bound to them.
+    fn bind(
+        &self,
+        sql: &str,
+        params: proto::Params,
+    ) -> Result<(duckdb::CachedStatement<'_>, Vec<proto::scalar_value::Kind>)> {
+        let params = StatementParams::try_from(params)?;
+        self.check_interrupt()?;
+        let (sql, names) = number_placeholders(sql);
+        let (sql, params) = expand_nested_params(&sql, params.positional(&names)?)?;
+        let statement = self.statement(&sql)?;
+        Ok((statement, params))
+    }
+
+    /// Prepares the statement, which is kept until the `Prepared` is dropped.
+    pub fn prepare<Q: AsRef<str>>(&self, sql: Q) -> Result<Prepared<'_>> {
+        self.check_interrupt()?;
+        let (sql, names) = number_placeholders(sql.as_ref());
+        let sql = self.last_statement(&sql)?;
+        let statement = self.conn.prepare(sql)?;
+        let schema = self.prepared_schema(&statement, sql);
+        Ok(Prepared {
+            gduck: self,
+            statement,
+            transaction: transaction_change(sql),
+            sql: sql.to_owned(),
+            names,
+            schema,
+        })
+    }
+
+    // DuckDB tells the result types of a prepared statement, unless they depend on its params,
+    // but not the names of its columns. DESCRIBE tells both without running the query, and
+    // the schema is taken from a query of NULLs of those types, prepared apart from the
+    // statement cache. Statements other than queries cannot be described.
+    fn prepared_schema(
+        &self,
+        statement: &duckdb::Statement<'_>,
+        sql: &str,
+    ) -> Option<proto::Schema> {
+        let sql = sql.trim();
+        let describe = format!("DESCRIBE {}", sql.strip_suffix(";").unwrap_or(sql));
+        let nulls = vec![duckdb::types::Null; statement.parameter_count()];
+        let columns = self
+            .conn
+            .prepare(&describe)
+            .ok()?
+            .query_map(duckdb::params_from_iter(nulls), |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .ok()?
+            .collect::<duckdb::Result<Vec<(String, String)>>>()
+            .ok()?;
+        // duckdb-rs panics for a column the statement does not have.
+        if columns.is_empty()
+            || (0..columns.len()).any(|i| {
+                statement.column_logical_type(i).id() == duckdb::core::LogicalTypeId::Invalid
+            })
+        {
+            return None;
+        }
+        let nulls = columns
+            .iter()
+            .map(|(name, column_type)| format!("NULL::{} AS {}", column_type, identifier(name)))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let schema = self
+            .conn
+            .prepare(&format!("SELECT {} LIMIT 0", nulls))
+            .ok()?
+            .query_arrow([])
+            .ok()?
+            .get_schema();
+        Self::schema(schema, None).ok()
+    }
+
+    /// Starts appending rows to the table.
+    pub fn append(&self, append: proto::Append) -> Result<Append<'_>> {
+        self.check_interrupt()?;
+        let (appender, table) = if append.schema_name.is_empty() {
+            (
+                self.conn.appender(&append.table_name)?,
+                identifier(&append.table_name),
+            )
+        } else {
+            (
+                self.conn
+                    .appender_to_db(&append.table_name, &append.schema_name)?,
+                format!(
+                    "{}.{}",
+                    identifier(&append.schema_name),
+                    identifier(&append.table_name)
+                ),
+            )
+        };
+        let implicit = !self.in_transaction();
+        if implicit {
+            self.begin()?;
+        }
+        Ok(Append {
+            gduck: self,
+            appender: Some(appender),
+            table,
+            num_rows: 0,
+            implicit,
+        })
+    }
+
+    /// Appends record batches in Arrow IPC streaming format to the table, which is created
+    /// as a temporary table of their schema first if `create_temporary`.
+    pub fn load_arrow(
+        &self,
+        load: proto::query::LoadArrow,
+    ) -> Result<proto::response::QueryResult> {
+        self.check_interrupt()?;
+        // The table is created and filled in a transaction so that neither takes effect
+        // if the other fails.
+        let implicit = !self.in_transaction();
+        if implicit {
+            self.begin()?;
+        }
+        let loaded = self.create_and_append(load);
+        if implicit {
+            match loaded {
+                Ok(_) => {
+                    self.commit()?;
+                }
+                Err(_) => {
+                    if let Err(err) = self.rollback() {
+                        log::warn!("Failed to roll back the load: {}", err);
+                    }
+                }
+            }
+        }
+        loaded
+    }
+
+    fn create_and_append(
+        &self,
+        load: proto::query::LoadArrow,
+    ) -> Result<proto::response::QueryResult> {
+        if load.create_temporary {
+            let batches = arrow_ipc::reader::StreamReader::try_new(
+                std::io::Cursor::new(&load.arrow_ipc),
+                None,
+            )?;
+            let columns = batches
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| {
+                    // Dictionaries are appended as their values.
+                    let column_type = match field.data_type() {
+                        duckdb::arrow::datatypes::DataType::Dictionary(_, value_type) => {
+                            let values = field
+                                .as_ref()
+                                .clone()
+                                .with_data_type(value_type.as_ref().clone());
+                            proto::ColumnType::from_field(&values, None)?
+                        }
+                        _ => proto::ColumnType::from_field(field, None)?,
+                    };
+                    Ok(format!(
+                        "{} {}",
+                        identifier(field.name()),
+                        column_type.type_name
+                    ))
+                })
+                .collect::<Result<Vec<String>>>()?;
+            self.conn.execute_batch(&format!(
+                "CREATE {}TEMPORARY TABLE {} ({})",
+                if load.replace { "OR REPLACE " } else { "" },
+                identifier(&load.table_name),
+                columns.join(", ")
+            ))?;
+        }
+
+        let mut append = self.append(proto::Append {
+            table_name: load.table_name,
+            schema_name: String::new(),
+        })?;
+        append.append_rows(proto::AppendRows {
+            kind: Some(proto::append_rows::Kind::ArrowIpc(load.arrow_ipc)),
+        })?;
+        append.finish()
+    }
+
+    // Secret scoped to the URI, which is kept only while the statement accessing the URI runs.
+    fn secret(&self, uri: &Uri) -> Result<Option<Secret<'_>>> {
+        let name = secret_name(self.id);
+        match uri.create_secret(&name)? {
+            Some(create_secret) => {
+                self.conn.execute_batch(&create_secret)?;
+                Ok(Some(Secret { gduck: self, name }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Schema of a query result. The first batch of the result, if any, is required
+    /// for the values of ENUMs.
     pub(crate) fn schema(
         schema: std::sync::Arc<duckdb::arrow::datatypes::Schema>,
+        batch: Option<&duckdb::arrow::record_batch::RecordBatch>,
     ) -> Result<proto::Schema> {
         let columns = schema
             .fields()
             .into_iter()
-            .map(|field| {
-                proto::DataType::try_from(field.data_type().to_owned()).map(|column_type| {
-                    proto::Column {
-                        name: field.name().to_owned(),
-                        data_type: column_type as i32,
-                    }
+            .enumerate()
+            .map(|(i, field)| {
+                let column_type = proto::ColumnType::from_field(
+                    field,
+                    batch.map(|batch| batch.column(i).as_ref()),
+                )?;
+                Ok(proto::Column {
+                    name: field.name().to_owned(),
+                    data_type: column_type.data_type,
+                    column_type: Some(column_type),
                 })
             })
             .collect::<Result<prost::alloc::vec::Vec<proto::Column>>>()?;
@@ -39,17 +1037,119 @@ impl Gduck {
         Ok(proto::Schema { columns })
     }
 
+    pub fn begin(&self) -> Result<proto::response::QueryResult> {
+        self.conn.execute_batch("BEGIN TRANSACTION")?;
+        self.transaction.set(true);
+        Ok(proto::response::QueryResult {
+            kind: Some(proto::response::query_result::Kind::Ok(())),
+            ..Default::default()
+        })
+    }
+
+    // DuckDB closes the transaction even if COMMIT fails, e.g. by a conflict.
+    pub fn commit(&self) -> Result<proto::response::QueryResult> {
+        self.transaction.set(false);
+        self.conn.execute_batch("COMMIT")?;
+        Ok(proto::response::QueryResult {
+            kind: Some(proto::response::query_result::Kind::Ok(())),
+            ..Default::default()
+        })
+    }
+
+    pub fn rollback(&self) -> Result<proto::response::QueryResult> {
+        self.transaction.set(false);
+        self.conn.execute_batch("ROLLBACK")?;
+        Ok(proto::response::QueryResult {
+            kind: Some(proto::response::query_result::Kind::Ok(())),
+            ..Default::default()
+        })
+    }
+
+    /// Whether a transaction started by `begin` or a BEGIN statement is open.
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.get()
+    }
+
+    /// Rolls back the transaction if it has not been finished yet.
+    pub fn rollback_if_open(&self) -> Result<()> {
+        if self.in_transaction() {
+            log::info!("Rolling back the transaction left open");
+            self.rollback()?;
+        }
+        Ok(())
+    }
+
     pub fn execute<Q: AsRef<str>>(
         &self,
         sql: Q,
         params: proto::Params,
     ) -> Result<proto::response::QueryResult> {
-        let params: duckdb::ParamsFromIter<Vec<proto::scalar_value::Kind>> = params.try_into()?;
-        self.conn
-            .execute(sql.as_ref(), params)
-            .map_err(Error::from)?;
+        let (mut statement, params) = self.bind(sql.as_ref(), params)?;
+        self.execute_statement(&mut statement, params)
+    }
+
+    fn execute_statement(
+        &self,
+        statement: &mut duckdb::Statement<'_>,
+        params: Vec<proto::scalar_value::Kind>,
+    ) -> Result<proto::response::QueryResult> {
+        statement.execute(duckdb::params_from_iter(params))?;
         Ok(proto::response::QueryResult {
             kind: Some(proto::response::query_result::Kind::Ok(())),
+            ..Default::default()
+        })
+    }
+
+    /// Runs the statement once for each of `params`. They run in a transaction of their own
+    /// unless one is open, and are rolled back if one fails, so that a failed batch leaves
+    /// nothing applied without telling it.
+    pub fn execute_batch<Q: AsRef<str>>(
+        &self,
+        sql: Q,
+        params: Vec<proto::Params>,
+    ) -> Result<proto::response::QueryResult> {
+        let params = params
+            .into_iter()
+            .map(StatementParams::try_from)
+            .collect::<Result<Vec<StatementParams>>>()?;
+        self.check_interrupt()?;
+
+        let implicit = !self.in_transaction();
+        if implicit {
+            self.begin()?;
+        }
+        let (sql, names) = number_placeholders(sql.as_ref());
+        // Params with nested values of other shapes are run by other statements, which are
+        // taken from the cache.
+        let num_rows = params
+            .into_iter()
+            .map(|params| {
+                self.check_interrupt()?;
+                let (sql, params) = expand_nested_params(&sql, params.positional(&names)?)?;
+                let mut statement = self.statement(&sql)?;
+                Ok(statement.execute(duckdb::params_from_iter(params))? as u64)
+            })
+            .collect::<Result<Vec<u64>>>();
+        if implicit {
+            match num_rows {
+                Ok(_) => {
+                    self.commit()?;
+                }
+                Err(_) => {
+                    if let Err(err) = self.rollback() {
+                        log::warn!("Failed to roll back the batch: {}", err);
+                    }
+                }
+            }
+        }
+
+        Ok(proto::response::QueryResult {
+            kind: Some(proto::response::query_result::Kind::ExecutedBatch(
+                proto::ExecutedBatch {
+                    num_rows: num_rows?,
+                },
+            )),
+            ..Default::default()
         })
     }
 
@@ -58,14 +1158,28 @@ impl Gduck {
         sql: Q,
         params: proto::Params,
     ) -> Result<proto::response::QueryResult> {
-        let params: duckdb::ParamsFromIter<Vec<proto::scalar_value::Kind>> = params.try_into()?;
-        self.conn.query_row_and_then(sql.as_ref(), params, |row| {
-            let kind = row.get::<usize, proto::scalar_value::Kind>(0)?;
-            Ok(proto::response::QueryResult {
-                kind: Some(proto::response::query_result::Kind::Value(
-                    proto::ScalarValue { kind: Some(kind) },
-                )),
-            })
+        let (mut statement, params) = self.bind(sql.as_ref(), params)?;
+        self.query_statement_value(&mut statement, params)
+    }
+
+    fn query_statement_value(
+        &self,
+        statement: &mut duckdb::Statement<'_>,
+        params: Vec<proto::scalar_value::Kind>,
+    ) -> Result<proto::response::QueryResult> {
+        let batch = statement
+            .query_arrow(duckdb::params_from_iter(params))?
+            .find(|batch| batch.num_rows() > 0)
+            .ok_or(duckdb::Error::QueryReturnedNoRows)?;
+        if batch.num_columns() == 0 {
+            return Err(duckdb::Error::InvalidColumnIndex(0).into());
+        }
+        let kind = crate::value::value(batch.schema().field(0), batch.column(0).as_ref(), 0)?;
+        Ok(proto::response::QueryResult {
+            kind: Some(proto::response::query_result::Kind::Value(
+                proto::ScalarValue { kind: Some(kind) },
+            )),
+            ..Default::default()
         })
     }
 
@@ -74,37 +1188,204 @@ impl Gduck {
         sql: Q,
         params: proto::Params,
     ) -> Result<proto::response::QueryResult> {
-        let params: duckdb::ParamsFromIter<Vec<proto::scalar_value::Kind>> = params.try_into()?;
-
-        let mut statement = self.conn.prepare(sql.as_ref())?;
+        let (mut statement, params) = self.bind(sql.as_ref(), params)?;
+        self.query_statement_rows(&mut statement, params)
+    }
 
-        let rows = statement.query_and_then(params, |row| {
-            let mut values = prost::alloc::vec![];
-            for i in 0.. {
-                match row.get::<usize, proto::scalar_value::Kind>(i) {
-                    Ok(kind) => {
-                        values.push(proto::ScalarValue { kind: Some(kind) });
-                    }
-                    Err(duckdb::Error::InvalidColumnIndex(_)) => {
-                        break;
-                    }
-                    Err(err) => {
-                        return Err(err);
-                    }
-                }
-            }
-            Ok(proto::Row { values })
-        }).and_then(|rows| {
-            rows.collect::<std::result::Result<prost::alloc::vec::Vec<proto::Row>, duckdb::Error>>()
-        }).map_err(crate::error::Error::from)?;
+    fn query_statement_rows(
+        &self,
+        statement: &mut duckdb::Statement<'_>,
+        params: Vec<proto::scalar_value::Kind>,
+    ) -> Result<proto::response::QueryResult> {
+        let batches = statement.query_arrow(duckdb::params_from_iter(params))?;
+        let arrow_schema = batches.get_schema();
+        let mut batches = batches.peekable();
+        let schema = Self::schema(arrow_schema, batches.peek())?;
 
-        let schema = Self::schema(statement.schema())?;
+        let mut rows = prost::alloc::vec![];
+        for batch in batches {
+            self.check_interrupt()?;
+            rows.extend(crate::value::rows(&batch)?);
+        }
 
         Ok(proto::response::QueryResult {
             kind: Some(proto::response::query_result::Kind::Rows(proto::Rows {
                 schema: Some(schema),
                 rows,
             })),
+            ..Default::default()
+        })
+    }
+
+    /// Runs the query and passes its result to `sink` in pieces: the schema first,
+    /// then rows in batches of `batch_size` and finally an end-of-rows marker.
+    ///
+    /// Rows are fetched from DuckDB as they are sent, so that the result is not held in memory.
+    pub fn stream_rows<Q: AsRef<str>, F: FnMut(proto::response::QueryResult) -> Result<()>>(
+        &self,
+        sql: Q,
+        params: proto::Params,
+        batch_size: usize,
+        sink: F,
+    ) -> Result<()> {
+        let (mut statement, params) = self.bind(sql.as_ref(), params)?;
+        self.stream_statement_rows(&mut statement, params, batch_size, sink)
+    }
+
+    fn stream_statement_rows<F: FnMut(proto::response::QueryResult) -> Result<()>>(
+        &self,
+        statement: &mut duckdb::Statement<'_>,
+        params: Vec<proto::scalar_value::Kind>,
+        batch_size: usize,
+        mut sink: F,
+    ) -> Result<()> {
+        let batch_size = if batch_size == 0 {
+            DEFAULT_BATCH_SIZE
+        } else {
+            batch_size
+        };
+        let (schema, batches) = Self::stream_batches(statement, params)?;
+        self.send_rows(schema, batches, batch_size, &mut sink)
+    }
+
+    // Schema and record batches of the result, which are fetched from DuckDB as they are read.
+    fn stream_batches<'s, 'c>(
+        statement: &'s mut duckdb::Statement<'c>,
+        params: Vec<proto::scalar_value::Kind>,
+    ) -> Result<(
+        duckdb::arrow::datatypes::SchemaRef,
+        impl Iterator<Item = duckdb::Result<duckdb::arrow::record_batch::RecordBatch>> + use<'s, 'c>,
+    )> {
+        let schema = statement
+            .stream_arrow(duckdb::params_from_iter(params))?
+            .get_schema();
+        // Chunks are fetched by `step`, since the iterator of duckdb-rs panics on a query
+        // failing while its rows are fetched.
+        let batches = std::iter::from_fn(move || {
+            statement
+                .step()
+                .transpose()
+                .map(|array| Ok(duckdb::arrow::record_batch::RecordBatch::from(&array?)))
+        });
+        Ok((schema, batches))
+    }
+
+    fn send_rows<I, F>(
+        &self,
+        schema: duckdb::arrow::datatypes::SchemaRef,
+        batches: I,
+        batch_size: usize,
+        sink: &mut F,
+    ) -> Result<()>
+    where
+        I: Iterator<Item = duckdb::Result<duckdb::arrow::record_batch::RecordBatch>>,
+        F: FnMut(proto::response::QueryResult) -> Result<()>,
+    {
+        let mut batches = batches.peekable();
+        sink(proto::response::QueryResult {
+            kind: Some(proto::response::query_result::Kind::Schema(Self::schema(
+                schema,
+                batches.peek().and_then(|batch| batch.as_ref().ok()),
+            )?)),
+            ..Default::default()
+        })?;
+
+        let mut num_rows: u64 = 0;
+        let mut batch = Vec::with_capacity(batch_size);
+        for record_batch in batches {
+            let record_batch = record_batch?;
+            self.check_interrupt()?;
+            for row in crate::value::rows(&record_batch)? {
+                batch.push(row);
+                num_rows += 1;
+                if batch.len() >= batch_size {
+                    sink(proto::response::QueryResult {
+                        kind: Some(proto::response::query_result::Kind::RowBatch(
+                            proto::RowBatch {
+                                rows: std::mem::replace(&mut batch, Vec::with_capacity(batch_size)),
+                            },
+                        )),
+                        ..Default::default()
+                    })?;
+                }
+            }
+        }
+        if !batch.is_empty() {
+            sink(proto::response::QueryResult {
+                kind: Some(proto::response::query_result::Kind::RowBatch(
+                    proto::RowBatch { rows: batch },
+                )),
+                ..Default::default()
+            })?;
+        }
+
+        sink(proto::response::QueryResult {
+            kind: Some(proto::response::query_result::Kind::EndOfRows(
+                proto::EndOfRows { num_rows },
+            )),
+            ..Default::default()
+        })
+    }
+
+    pub fn query_arrow<Q: AsRef<str>, F: FnMut(proto::response::QueryResult) -> Result<()>>(
+        &self,
+        sql: Q,
+        params: proto::Params,
+        sink: F,
+    ) -> Result<()> {
+        let (mut statement, params) = self.bind(sql.as_ref(), params)?;
+        self.query_statement_arrow(&mut statement, params, sink)
+    }
+
+    // The Arrow IPC stream is sent in chunks as the record batches are fetched, followed by
+    // EndOfRows, so that results larger than a message can hold are not buffered.
+    fn query_statement_arrow<F: FnMut(proto::response::QueryResult) -> Result<()>>(
+        &self,
+        statement: &mut duckdb::Statement<'_>,
+        params: Vec<proto::scalar_value::Kind>,
+        mut sink: F,
+    ) -> Result<()> {
+        fn send<F: FnMut(proto::response::QueryResult) -> Result<()>>(
+            bytes: Vec<u8>,
+            sink: &mut F,
+        ) -> Result<()> {
+            sink(proto::response::QueryResult {
+                kind: Some(proto::response::query_result::Kind::ArrowIpc(bytes)),
+                ..Default::default()
+            })
+        }
+
+        let (schema, batches) = Self::stream_batches(statement, params)?;
+        let interoperable = crate::value::interoperable_schema(&schema);
+        // Batches are written as DuckDB returns them unless some of their columns are converted.
+        let convert = interoperable != schema;
+        let mut writer = arrow_ipc::writer::StreamWriter::try_new(Vec::new(), &interoperable)?;
+        let mut num_rows: u64 = 0;
+        for batch in batches {
+            let batch = batch?;
+            self.check_interrupt()?;
+            num_rows += batch.num_rows() as u64;
+            if convert {
+                writer.write(&crate::value::interoperable(&batch, interoperable.clone())?)?;
+            } else {
+                writer.write(&batch)?;
+            }
+            let buffer = writer.get_mut();
+            while buffer.len() >= ARROW_IPC_CHUNK_SIZE {
+                let rest = buffer.split_off(ARROW_IPC_CHUNK_SIZE);
+                send(std::mem::replace(buffer, rest), &mut sink)?;
+            }
+        }
+        let buffer = writer.into_inner()?;
+        for chunk in buffer.chunks(ARROW_IPC_CHUNK_SIZE) {
+            send(chunk.to_vec(), &mut sink)?;
+        }
+
+        sink(proto::response::QueryResult {
+            kind: Some(proto::response::query_result::Kind::EndOfRows(
+                proto::EndOfRows { num_rows },
+            )),
+            ..Default::default()
         })
     }
 
@@ -118,24 +1399,1171 @@ impl Gduck {
         self.execute(ctas_query, params)
     }
 
-    pub fn query_as_parquet<Q: AsRef<str>>(
+    /// Writes the result of the query to the URI in the format, Parquet if it is None.
+    pub fn export<Q: AsRef<str>>(
         &self,
         sql: Q,
         params: proto::Params,
         uri: Uri,
+        format: Option<proto::query::export_query::Format>,
     ) -> Result<proto::response::QueryResult> {
+        if let Uri::Azure { .. } = uri {
+            // DuckDB's azure extension only reads
+            return Err(Error::UnsupportedUri(uri.to_string()));
+        }
         let sql = sql.as_ref().trim();
+        let format = format.unwrap_or(proto::query::export_query::Format::Parquet(
+            proto::query::export_query::Parquet {},
+        ));
         let query = format!(
-            "COPY ({}) TO '{}' (FORMAT PARQUET)",
+            "COPY ({}) TO {} ({})",
             sql.strip_suffix(";").unwrap_or(sql),
-            uri
+            crate::uri::literal(&uri.to_string()),
+            format.copy_options()
         );
-        self.execute(query, params).and_then(|_| {
-            Ok(proto::response::QueryResult {
-                kind: Some(proto::response::query_result::Kind::ParquetFile(
-                    proto::Location::try_from(uri)?,
-                )),
+
+        let _secret = self.secret(&uri)?;
+        let (mut statement, params) = self.bind(&query, params)?;
+        // COPY returns the number of the rows written as that of changed rows.
+        let num_rows = statement.execute(duckdb::params_from_iter(params))?;
+        let location = proto::Location::try_from(uri)?;
+        #[allow(deprecated)]
+        let result = proto::response::QueryResult {
+            parquet_file: matches!(format, proto::query::export_query::Format::Parquet(_))
+                .then(|| location.clone()),
+            kind: Some(proto::response::query_result::Kind::Exported(
+                proto::Exported {
+                    location: Some(location),
+                    num_rows: num_rows as u64,
+                },
+            )),
+        };
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::scalar_value::Kind;
+
+    fn gduck() -> Gduck {
+        let config = duckdb::Config::try_from(proto::Connect::default()).unwrap();
+        Gduck::new(duckdb::Connection::open_in_memory_with_flags(config).unwrap())
+    }
+
+    fn named(params: &[(&str, Kind)]) -> proto::Params {
+        proto::Params {
+            named_params: params
+                .iter()
+                .map(|(name, kind)| {
+                    (
+                        name.to_string(),
+                        proto::ScalarValue {
+                            kind: Some(kind.clone()),
+                        },
+                    )
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn value(result: proto::response::QueryResult) -> Kind {
+        match result.kind {
+            Some(proto::response::query_result::Kind::Value(proto::ScalarValue {
+                kind: Some(kind),
+            })) => kind,
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn number_placeholders_in_order_of_appearance() {
+        let (sql, names) = number_placeholders("SELECT $b + $a * $b, $a");
+        assert_eq!(sql, "SELECT $1 + $2 * $1, $2");
+        assert_eq!(names, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn number_placeholders_skips_literals_and_comments() {
+        let sql = "SELECT '$a''$b', E'\\'$c', \"$d\"\"x\", $$ $e $$, $t$ $f $t$, a$g -- $h\n, /* $i /* $j */ */ $k";
+        let (numbered, names) = number_placeholders(sql);
+        assert_eq!(names, vec!["k"]);
+        assert_eq!(numbered, sql.replace("$k", "$1"));
+    }
+
+    #[test]
+    fn number_placeholders_keeps_positional_placeholders() {
+        let (sql, names) = number_placeholders("SELECT ?, $1");
+        assert!(matches!(sql, std::borrow::Cow::Borrowed("SELECT ?, $1")));
+        assert!(names.is_empty());
+    }
+
+    fn list(values: Vec<Kind>) -> Kind {
+        Kind::ListValue(proto::ListValue {
+            values: values
+                .into_iter()
+                .map(|kind| proto::ScalarValue { kind: Some(kind) })
+                .collect(),
+        })
+    }
+
+    fn string(s: &str) -> Kind {
+        Kind::StrValue(String::from(s))
+    }
+
+    #[test]
+    fn expand_nested_params_to_their_leaves() {
+        let params = vec![
+            Kind::IntValue(1),
+            list(vec![Kind::IntValue(2), list(vec![Kind::IntValue(3)])]),
+        ];
+        let (sql, leaves) =
+            expand_nested_params("SELECT ?, '?', ?::INTEGER[][]", params.clone()).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT $1, '?', list_value($2, list_value($3))::INTEGER[][]"
+        );
+        assert_eq!(
+            leaves,
+            vec![Kind::IntValue(1), Kind::IntValue(2), Kind::IntValue(3)]
+        );
+        // A placeholder without its param
+        assert!(matches!(
+            expand_nested_params("SELECT $2, $3", params),
+            Err(Error::InvalidRequest(_))
+        ));
+
+        let (sql, leaves) = expand_nested_params("SELECT $1", vec![Kind::IntValue(1)]).unwrap();
+        assert!(matches!(sql, std::borrow::Cow::Borrowed("SELECT $1")));
+        assert_eq!(leaves, vec![Kind::IntValue(1)]);
+    }
+
+    #[test]
+    fn bind_nested_params_with_quotes_and_commas() {
+        let gduck = gduck();
+        let strings = list(vec![
+            string("c,d"),
+            string("it's"),
+            string("[\"x\"]"),
+            string("NULL"),
+            Kind::NullValue(0),
+        ]);
+        let fields = Kind::StructValue(proto::StructValue {
+            fields: vec![
+                proto::struct_value::Field {
+                    name: String::from("a:b, \"c\""),
+                    value: Some(proto::ScalarValue {
+                        kind: Some(string("{'x': 1}, y=z")),
+                    }),
+                },
+                proto::struct_value::Field {
+                    name: String::from("l"),
+                    value: Some(proto::ScalarValue {
+                        kind: Some(strings.clone()),
+                    }),
+                },
+            ],
+        });
+        let map = Kind::MapValue(proto::MapValue {
+            entries: vec![proto::map_value::Entry {
+                key: Some(proto::ScalarValue {
+                    kind: Some(string("k,'1'=")),
+                }),
+                value: Some(proto::ScalarValue {
+                    kind: Some(string(" v, 2 ")),
+                }),
+            }],
+        });
+        for param in [strings, fields, map] {
+            let result = gduck
+                .query_value("SELECT $v", named(&[("v", param.clone())]))
+                .unwrap();
+            assert_eq!(value(result), param);
+        }
+
+        // Nested params of each shape in a batch and in a prepared statement
+        gduck
+            .execute("CREATE TABLE t (l VARCHAR[])", proto::Params::default())
+            .unwrap();
+        let params = [
+            vec![string("a,b")],
+            vec![],
+            vec![string("'c'"), string("d")],
+        ]
+        .into_iter()
+        .map(|values| proto::Params {
+            params: vec![proto::ScalarValue {
+                kind: Some(list(values)),
+            }],
+            ..Default::default()
+        })
+        .collect();
+        gduck
+            .execute_batch("INSERT INTO t VALUES (?)", params)
+            .unwrap();
+        let mut count = gduck
+            .prepare("SELECT COUNT(*) FROM t WHERE l = $l::VARCHAR[]")
+            .unwrap();
+        for values in [
+            vec![string("a,b")],
+            vec![],
+            vec![string("'c'"), string("d")],
+        ] {
+            let counted = results(|sink| {
+                count.run(
+                    proto::query::ExecutePrepared {
+                        handle: 1,
+                        params: Some(named(&[("l", list(values))])),
+                        output: proto::query::execute_prepared::Output::Value.into(),
+                        batch_size: 0,
+                    },
+                    sink,
+                )
+            });
+            assert_eq!(
+                counted.unwrap(),
+                vec![proto::response::query_result::Kind::Value(
+                    proto::ScalarValue {
+                        kind: Some(Kind::IntValue(1))
+                    }
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn bind_named_params() {
+        let gduck = gduck();
+        let result = gduck
+            .query_value(
+                "SELECT $b - $a * $b",
+                named(&[("a", Kind::IntValue(2)), ("$b", Kind::IntValue(5))]),
+            )
+            .unwrap();
+        assert_eq!(value(result), Kind::IntValue(-5));
+    }
+
+    #[test]
+    fn bind_positional_params_to_named_placeholders() {
+        let gduck = gduck();
+        let params = proto::Params {
+            params: vec![
+                proto::ScalarValue {
+                    kind: Some(Kind::IntValue(5)),
+                },
+                proto::ScalarValue {
+                    kind: Some(Kind::IntValue(2)),
+                },
+            ],
+            ..Default::default()
+        };
+        let result = gduck.query_value("SELECT $b - $a * $b", params).unwrap();
+        assert_eq!(value(result), Kind::IntValue(-5));
+    }
+
+    #[test]
+    fn reject_positional_and_named_params_together() {
+        let gduck = gduck();
+        let mut params = named(&[("a", Kind::IntValue(1))]);
+        params.params.push(proto::ScalarValue {
+            kind: Some(Kind::IntValue(2)),
+        });
+        assert!(matches!(
+            gduck.query_value("SELECT $a, ?", params),
+            Err(Error::InvalidRequest(_))
+        ));
+        assert!(gduck
+            .query_value("SELECT $a, ?", named(&[("a", Kind::IntValue(1))]))
+            .is_err());
+    }
+
+    #[test]
+    fn reject_unknown_and_missing_named_params() {
+        let gduck = gduck();
+        assert!(matches!(
+            gduck.query_value(
+                "SELECT $a",
+                named(&[("a", Kind::IntValue(1)), ("b", Kind::IntValue(2))])
+            ),
+            Err(Error::InvalidRequest(message)) if message.contains("$b")
+        ));
+        assert!(matches!(
+            gduck.query_value("SELECT $a + $b", named(&[("a", Kind::IntValue(1))])),
+            Err(Error::InvalidRequest(message)) if message.contains("$b")
+        ));
+    }
+
+    fn results<
+        F: FnOnce(&mut dyn FnMut(proto::response::QueryResult) -> Result<()>) -> Result<()>,
+    >(
+        run: F,
+    ) -> Result<Vec<proto::response::query_result::Kind>> {
+        let mut results = Vec::new();
+        run(&mut |result| {
+            results.push(result.kind.unwrap());
+            Ok(())
+        })?;
+        Ok(results)
+    }
+
+    fn arrow_ipc(batches: &[duckdb::arrow::record_batch::RecordBatch]) -> Vec<u8> {
+        let mut writer =
+            arrow_ipc::writer::StreamWriter::try_new(Vec::new(), &batches[0].schema()).unwrap();
+        for batch in batches {
+            writer.write(batch).unwrap();
+        }
+        writer.into_inner().unwrap()
+    }
+
+    // Batch of `num_rows` rows with an INTEGER column and a dictionary encoded VARCHAR column
+    fn dictionary_batch(start: i32, num_rows: i32) -> duckdb::arrow::record_batch::RecordBatch {
+        use duckdb::arrow::array::{ArrayRef, DictionaryArray, Int32Array};
+        use duckdb::arrow::datatypes::Int32Type;
+
+        let names = ["a", "b", "c"];
+        duckdb::arrow::record_batch::RecordBatch::try_from_iter([
+            (
+                "i",
+                std::sync::Arc::new(Int32Array::from_iter_values(start..start + num_rows))
+                    as ArrayRef,
+            ),
+            (
+                "s",
+                std::sync::Arc::new(
+                    (start..start + num_rows)
+                        .map(|i| names[i as usize % names.len()])
+                        .collect::<DictionaryArray<Int32Type>>(),
+                ) as ArrayRef,
+            ),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trip_hugeint_and_uhugeint() {
+        let gduck = gduck();
+        let round_trip = |sql: &str, kind: Kind, text: String| {
+            let result = gduck
+                .query_value(sql, named(&[("v", kind.clone())]))
+                .unwrap();
+            assert_eq!(value(result), kind);
+            let sql = format!("SELECT ({})::VARCHAR", sql.trim_start_matches("SELECT "));
+            let result = gduck.query_value(&sql, named(&[("v", kind)])).unwrap();
+            assert_eq!(value(result), Kind::StrValue(text));
+        };
+        for hugeint in [i128::MIN, -(1 << 100) + 7, -1, i128::MAX] {
+            round_trip(
+                "SELECT $v::HUGEINT",
+                Kind::HugeintValue(proto::HugeInt::from(hugeint)),
+                hugeint.to_string(),
+            );
+        }
+        for uhugeint in [u128::MAX, 1 << 64, 0] {
+            round_trip(
+                "SELECT $v::UHUGEINT",
+                Kind::UhugeintValue(proto::UHugeInt::from(uhugeint)),
+                uhugeint.to_string(),
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip_timetz_and_timestamp_ns() {
+        let gduck = gduck();
+        for offset_seconds in [
+            20_700,
+            -34_200,
+            0,
+            proto::TimeTz::MAX_OFFSET_SECONDS,
+            -proto::TimeTz::MAX_OFFSET_SECONDS,
+        ] {
+            let timetz = Kind::TimetzValue(proto::TimeTz {
+                time: Some(proto::Time {
+                    hours: 23,
+                    minutes: 4,
+                    seconds: 5,
+                    nanos: 678_000,
+                }),
+                offset_seconds,
+            });
+            let result = gduck
+                .query_value("SELECT $t::TIMETZ", named(&[("t", timetz.clone())]))
+                .unwrap();
+            assert_eq!(value(result), timetz, "{}", offset_seconds);
+        }
+
+        for (seconds, nanos) in [(1_700_000_000, 123_456_789), (-1, 999_999_999)] {
+            let timestamp = Kind::DatetimeValue(prost_types::Timestamp { seconds, nanos });
+            let result = gduck
+                .query_value(
+                    "SELECT $t::TIMESTAMP_NS",
+                    named(&[("t", timestamp.clone())]),
+                )
+                .unwrap();
+            assert_eq!(value(result), timestamp);
+        }
+    }
+
+    #[test]
+    fn type_names_of_columns() {
+        let gduck = gduck();
+        gduck
+            .execute(
+                "CREATE TYPE mood AS ENUM ('ok', 'it''s')",
+                proto::Params::default(),
+            )
+            .unwrap();
+        let columns = "SELECT 1.5::DECIMAL(18,3) AS d, 'ok'::mood AS e, [[1]] AS l, \
+                       {'a b': 'x', 'n': [1, 2, 3]::INTEGER[3]} AS s, \
+                       MAP {'k': 1.5::DECIMAL(4,1)} AS m";
+        let result = gduck.query_rows(columns, proto::Params::default()).unwrap();
+        let Some(proto::response::query_result::Kind::Rows(proto::Rows {
+            schema: Some(schema),
+            ..
+        })) = result.kind
+        else {
+            panic!("unexpected result {:?}", result);
+        };
+        let type_names = schema
+            .columns
+            .iter()
+            .map(|column| column.column_type.as_ref().unwrap().type_name.clone())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            type_names,
+            vec![
+                "DECIMAL(18,3)",
+                "ENUM('ok', 'it''s')",
+                "INTEGER[][]",
+                "STRUCT(\"a b\" VARCHAR, \"n\" INTEGER[3])",
+                "MAP(VARCHAR, DECIMAL(4,1))",
+            ]
+        );
+        // DuckDB reads the names as the types of the columns.
+        for (column, type_name) in schema.columns.iter().zip(type_names) {
+            let sql = format!(
+                "SELECT typeof(NULL::{}) = typeof({}) FROM ({})",
+                type_name, column.name, columns
+            );
+            let result = gduck.query_value(&sql, proto::Params::default()).unwrap();
+            assert_eq!(value(result), Kind::BoolValue(true), "{}", type_name);
+        }
+    }
+
+    #[test]
+    fn bind_decimal() {
+        let gduck = gduck();
+        let decimal = |value: &str| {
+            Kind::DecimalValue(proto::Decimal {
+                value: String::from(value),
+                precision: 0,
+                scale: 0,
+            })
+        };
+        let result = gduck
+            .query_value(
+                "SELECT $d::DECIMAL(10,2)",
+                named(&[("d", decimal("-12.5"))]),
+            )
+            .unwrap();
+        assert_eq!(
+            value(result),
+            Kind::DecimalValue(proto::Decimal {
+                value: String::from("-12.50"),
+                precision: 10,
+                scale: 2,
             })
+        );
+
+        gduck
+            .execute("CREATE TABLE t (d DECIMAL(18,3))", proto::Params::default())
+            .unwrap();
+        gduck
+            .execute(
+                "INSERT INTO t VALUES ($d)",
+                named(&[("d", decimal("123456789012345.678"))]),
+            )
+            .unwrap();
+        let result = gduck
+            .query_value("SELECT d FROM t", proto::Params::default())
+            .unwrap();
+        assert_eq!(
+            value(result),
+            Kind::DecimalValue(proto::Decimal {
+                value: String::from("123456789012345.678"),
+                precision: 18,
+                scale: 3,
+            })
+        );
+
+        let result = gduck
+            .query_value("SELECT $d", named(&[("d", decimal("-0.50"))]))
+            .unwrap();
+        assert_eq!(
+            value(result),
+            Kind::DecimalValue(proto::Decimal {
+                value: String::from("-0.5"),
+                precision: 1,
+                scale: 1,
+            })
+        );
+
+        gduck
+            .execute(
+                "CREATE TABLE payments (amount DECIMAL(10,2))",
+                proto::Params::default(),
+            )
+            .unwrap();
+        let mut append = gduck
+            .append(proto::Append {
+                table_name: String::from("payments"),
+                schema_name: String::new(),
+            })
+            .unwrap();
+        append
+            .append_rows(proto::AppendRows {
+                kind: Some(proto::append_rows::Kind::Rows(proto::RowBatch {
+                    rows: ["19.99", "20.00", "0.1"]
+                        .into_iter()
+                        .map(|amount| proto::Row {
+                            values: vec![proto::ScalarValue {
+                                kind: Some(decimal(amount)),
+                            }],
+                        })
+                        .collect(),
+                })),
+            })
+            .unwrap();
+        append.finish().unwrap();
+        for (amount, count) in [("19.99", 1), ("20", 1), ("0.1", 1), ("19.9", 0)] {
+            let result = gduck
+                .query_value(
+                    "SELECT count(*) FROM payments WHERE amount = $amount",
+                    named(&[("amount", decimal(amount))]),
+                )
+                .unwrap();
+            assert_eq!(value(result), Kind::IntValue(count), "{}", amount);
+        }
+    }
+
+    #[test]
+    fn stream_rows_in_batches() {
+        let gduck = gduck();
+        let results = results(|sink| {
+            gduck.stream_rows(
+                "SELECT * FROM range(5000)",
+                proto::Params::default(),
+                3000,
+                sink,
+            )
         })
+        .unwrap();
+        let batch_sizes = results
+            .iter()
+            .filter_map(|result| match result {
+                proto::response::query_result::Kind::RowBatch(batch) => Some(batch.rows.len()),
+                _ => None,
+            })
+            .collect::<Vec<usize>>();
+        assert!(matches!(
+            results.first(),
+            Some(proto::response::query_result::Kind::Schema(_))
+        ));
+        assert_eq!(batch_sizes, vec![3000, 2000]);
+        assert_eq!(
+            results.last(),
+            Some(&proto::response::query_result::Kind::EndOfRows(
+                proto::EndOfRows { num_rows: 5000 }
+            ))
+        );
+        assert!(!gduck.in_transaction());
+    }
+
+    #[test]
+    fn stream_rows_of_failing_query() {
+        let gduck = gduck();
+        // Casting fails when the rows are fetched.
+        let sql = "SELECT CAST(s AS INTEGER) FROM (VALUES ('1'), ('a')) t(s)";
+        let failed = |streamed: Result<Vec<proto::response::query_result::Kind>>| {
+            matches!(
+                streamed,
+                Err(Error::DatabaseError {
+                    kind: crate::error::DatabaseErrorKind::Conversion,
+                    message,
+                }) if message.contains("'a'")
+            )
+        };
+        let streamed = results(|sink| gduck.stream_rows(sql, proto::Params::default(), 0, sink));
+        assert!(failed(streamed));
+        assert!(!gduck.in_transaction());
+
+        gduck.begin().unwrap();
+        let streamed = results(|sink| gduck.stream_rows(sql, proto::Params::default(), 0, sink));
+        assert!(failed(streamed));
+        gduck.rollback().unwrap();
+        assert!(!gduck.in_transaction());
+    }
+
+    #[test]
+    fn query_arrow_in_chunks() {
+        let gduck = gduck();
+        let results = results(|sink| {
+            gduck.query_arrow(
+                "SELECT i, i % 2 = 0 AS even FROM range(300000) t(i)",
+                proto::Params::default(),
+                sink,
+            )
+        })
+        .unwrap();
+        let chunks = results
+            .iter()
+            .filter_map(|result| match result {
+                proto::response::query_result::Kind::ArrowIpc(chunk) => Some(chunk.as_slice()),
+                _ => None,
+            })
+            .collect::<Vec<&[u8]>>();
+        assert!(chunks.len() > 1, "{}", chunks.len());
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.len() <= ARROW_IPC_CHUNK_SIZE));
+        assert_eq!(
+            results.last(),
+            Some(&proto::response::query_result::Kind::EndOfRows(
+                proto::EndOfRows { num_rows: 300000 }
+            ))
+        );
+
+        let reader =
+            arrow_ipc::reader::StreamReader::try_new(std::io::Cursor::new(chunks.concat()), None)
+                .unwrap();
+        let schema = reader.schema();
+        assert_eq!(
+            schema.field(1).data_type(),
+            &duckdb::arrow::datatypes::DataType::Boolean
+        );
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        assert_eq!(
+            batches.iter().map(|batch| batch.num_rows()).sum::<usize>(),
+            300000
+        );
+    }
+
+    #[test]
+    fn cancel_streaming_rows() {
+        let gduck = gduck();
+        let canceller = gduck.canceller();
+        let mut num_results = 0;
+        let streamed = gduck
+            .stream_rows(
+                "SELECT * FROM range(100000000)",
+                proto::Params::default(),
+                1000,
+                |_| {
+                    num_results += 1;
+                    canceller.cancel();
+                    Ok(())
+                },
+            )
+            .map_err(|err| gduck.interruption(err));
+        assert!(matches!(streamed, Err(Error::Cancelled)));
+        assert!(num_results < 10);
+        assert!(!gduck.in_transaction());
+
+        gduck.start_request(None);
+        let result = gduck
+            .query_value("SELECT 1", proto::Params::default())
+            .unwrap();
+        assert_eq!(value(result), Kind::IntValue(1));
+    }
+
+    #[test]
+    fn execute_batch_in_transaction_opened_by_statement() {
+        let gduck = gduck();
+        gduck
+            .execute("CREATE TABLE t (i INTEGER)", proto::Params::default())
+            .unwrap();
+        gduck
+            .execute("BEGIN TRANSACTION", proto::Params::default())
+            .unwrap();
+        let params = (0..3)
+            .map(|i| proto::Params {
+                params: vec![proto::ScalarValue {
+                    kind: Some(Kind::IntValue(i)),
+                }],
+                ..Default::default()
+            })
+            .collect();
+        gduck
+            .execute_batch("INSERT INTO t VALUES (?)", params)
+            .unwrap();
+        // The batch is not committed but left in the transaction.
+        gduck.rollback().unwrap();
+        let result = gduck
+            .query_value("SELECT COUNT(*) FROM t", proto::Params::default())
+            .unwrap();
+        assert_eq!(value(result), Kind::IntValue(0));
+    }
+
+    #[test]
+    fn roll_back_failed_batch() {
+        let gduck = gduck();
+        gduck
+            .execute(
+                "CREATE TABLE t (i INTEGER PRIMARY KEY)",
+                proto::Params::default(),
+            )
+            .unwrap();
+        let params = [1, 2, 1]
+            .into_iter()
+            .map(|i| proto::Params {
+                params: vec![proto::ScalarValue {
+                    kind: Some(Kind::IntValue(i)),
+                }],
+                ..Default::default()
+            })
+            .collect();
+        assert!(gduck
+            .execute_batch("INSERT INTO t VALUES (?)", params)
+            .is_err());
+        assert!(!gduck.in_transaction());
+        let result = gduck
+            .query_value("SELECT COUNT(*) FROM t", proto::Params::default())
+            .unwrap();
+        assert_eq!(value(result), Kind::IntValue(0));
+    }
+
+    #[test]
+    fn track_transaction_state() {
+        let gduck = gduck();
+        gduck.begin().unwrap();
+        assert!(gduck.in_transaction());
+        // A failed statement leaves the transaction open until it is rolled back.
+        assert!(gduck
+            .execute("SELECT 'a'::INTEGER", proto::Params::default())
+            .is_err());
+        assert!(gduck.in_transaction());
+        gduck.commit().unwrap();
+        assert!(!gduck.in_transaction());
+
+        gduck
+            .execute("-- open\n  begin", proto::Params::default())
+            .unwrap();
+        assert!(gduck.in_transaction());
+        assert!(gduck.execute("BEGIN", proto::Params::default()).is_err());
+        assert!(gduck.in_transaction());
+        gduck
+            .execute("/* close */ END", proto::Params::default())
+            .unwrap();
+        assert!(!gduck.in_transaction());
+
+        let mut rollback = gduck.prepare("ROLLBACK").unwrap();
+        gduck.begin().unwrap();
+        results(|sink| {
+            rollback.run(
+                proto::query::ExecutePrepared {
+                    handle: 1,
+                    ..Default::default()
+                },
+                sink,
+            )
+        })
+        .unwrap();
+        assert!(!gduck.in_transaction());
+        assert!(gduck.rollback().is_err());
+        assert!(!gduck.in_transaction());
+    }
+
+    #[test]
+    fn track_transaction_state_of_each_statement() {
+        let gduck = gduck();
+        gduck
+            .execute("CREATE TABLE t (i INTEGER)", proto::Params::default())
+            .unwrap();
+        // Run twice as the second one takes the statement from the cache.
+        for _ in 0..2 {
+            gduck
+                .execute(
+                    "BEGIN; INSERT INTO t VALUES (1); COMMIT;",
+                    proto::Params::default(),
+                )
+                .unwrap();
+            assert!(!gduck.in_transaction());
+        }
+        let result = gduck
+            .query_value("SELECT COUNT(*) FROM t", proto::Params::default())
+            .unwrap();
+        assert_eq!(value(result), Kind::IntValue(2));
+
+        gduck
+            .execute("SELECT 1; BEGIN -- ; COMMIT", proto::Params::default())
+            .unwrap();
+        assert!(gduck.in_transaction());
+        assert!(gduck
+            .execute(
+                "INSERT INTO t VALUES (1); SELECT 'a'::INTEGER; COMMIT",
+                proto::Params::default()
+            )
+            .is_err());
+        assert!(gduck.in_transaction());
+        gduck.rollback().unwrap();
+        let result = gduck
+            .query_value("SELECT COUNT(*) FROM t", proto::Params::default())
+            .unwrap();
+        assert_eq!(value(result), Kind::IntValue(2));
+    }
+
+    #[test]
+    fn split_statements_outside_literals_and_comments() {
+        assert_eq!(
+            split_statements("SELECT ';'; SELECT \"a;b\" /* ; */ ;\n-- ;\n"),
+            vec!["SELECT ';'", " SELECT \"a;b\" /* ; */ "]
+        );
+        assert_eq!(split_statements("SELECT $x$;$x$"), vec!["SELECT $x$;$x$"]);
+        assert!(split_statements(" ; -- nothing").is_empty());
+    }
+
+    #[test]
+    fn append_arrow_batches_with_dictionary() {
+        let gduck = gduck();
+        gduck
+            .execute(
+                "CREATE TABLE t (i INTEGER, s VARCHAR)",
+                proto::Params::default(),
+            )
+            .unwrap();
+        let mut append = gduck
+            .append(proto::Append {
+                table_name: String::from("t"),
+                schema_name: String::new(),
+            })
+            .unwrap();
+        append
+            .append_rows(proto::AppendRows {
+                kind: Some(proto::append_rows::Kind::ArrowIpc(arrow_ipc(&[
+                    dictionary_batch(0, 5000),
+                    dictionary_batch(5000, 10),
+                ]))),
+            })
+            .unwrap();
+        assert_eq!(
+            append.finish().unwrap().kind,
+            Some(proto::response::query_result::Kind::Appended(
+                proto::Appended { num_rows: 5010 }
+            ))
+        );
+        let result = gduck
+            .query_value(
+                "SELECT COUNT(*) FILTER (WHERE s = ['a', 'b', 'c'][i % 3 + 1]) FROM t",
+                proto::Params::default(),
+            )
+            .unwrap();
+        assert_eq!(value(result), Kind::IntValue(5010));
+    }
+
+    #[test]
+    fn append_nested_values() {
+        let gduck = gduck();
+        gduck
+            .execute(
+                "CREATE TABLE t (i HUGEINT, l VARCHAR[], s STRUCT(\"a b\" VARCHAR), m MAP(VARCHAR, VARCHAR))",
+                proto::Params::default(),
+            )
+            .unwrap();
+        let strings = ["a,b", "it's", "{x}", "NULL", "[\"y\"]"];
+        let row = |i: i128, text: &str| proto::Row {
+            values: [
+                Kind::HugeintValue(proto::HugeInt::from(i)),
+                list(strings.iter().map(|s| string(s)).collect()),
+                Kind::StructValue(proto::StructValue {
+                    fields: vec![proto::struct_value::Field {
+                        name: String::from("a b"),
+                        value: Some(proto::ScalarValue {
+                            kind: Some(string(text)),
+                        }),
+                    }],
+                }),
+                Kind::MapValue(proto::MapValue {
+                    entries: vec![proto::map_value::Entry {
+                        key: Some(proto::ScalarValue {
+                            kind: Some(string(text)),
+                        }),
+                        value: Some(proto::ScalarValue {
+                            kind: Some(string("k=v")),
+                        }),
+                    }],
+                }),
+            ]
+            .into_iter()
+            .map(|kind| proto::ScalarValue { kind: Some(kind) })
+            .collect(),
+        };
+        let primitive = proto::Row {
+            values: vec![
+                proto::ScalarValue {
+                    kind: Some(Kind::HugeintValue(proto::HugeInt::from(i128::MIN))),
+                },
+                proto::ScalarValue {
+                    kind: Some(Kind::NullValue(0)),
+                },
+                proto::ScalarValue {
+                    kind: Some(Kind::NullValue(0)),
+                },
+                proto::ScalarValue {
+                    kind: Some(Kind::NullValue(0)),
+                },
+            ],
+        };
+        let mut append = gduck
+            .append(proto::Append {
+                table_name: String::from("t"),
+                schema_name: String::new(),
+            })
+            .unwrap();
+        append
+            .append_rows(proto::AppendRows {
+                kind: Some(proto::append_rows::Kind::Rows(proto::RowBatch {
+                    rows: vec![primitive, row(1, "it's"), row(2, "NULL")],
+                })),
+            })
+            .unwrap();
+        assert_eq!(
+            append.finish().unwrap().kind,
+            Some(proto::response::query_result::Kind::Appended(
+                proto::Appended { num_rows: 3 }
+            ))
+        );
+
+        let result = gduck
+            .query_value(
+                "SELECT count(*) FROM t WHERE i = $i",
+                named(&[("i", Kind::HugeintValue(proto::HugeInt::from(i128::MIN)))]),
+            )
+            .unwrap();
+        assert_eq!(value(result), Kind::IntValue(1));
+        for (i, text) in [(1, "it's"), (2, "NULL")] {
+            let result = gduck
+                .query_value(
+                    "SELECT count(*) FROM t WHERE i = $i AND l = $l AND s.\"a b\" = $text AND m[$text] = 'k=v'",
+                    named(&[
+                        ("i", Kind::IntValue(i)),
+                        ("l", list(strings.iter().map(|s| string(s)).collect())),
+                        ("text", string(text)),
+                    ]),
+                )
+                .unwrap();
+            assert_eq!(value(result), Kind::IntValue(1), "{}", text);
+        }
+    }
+
+    #[test]
+    fn roll_back_unfinished_append() {
+        let gduck = gduck();
+        gduck
+            .execute("CREATE TABLE t (i INTEGER)", proto::Params::default())
+            .unwrap();
+        let mut append = gduck
+            .append(proto::Append {
+                table_name: String::from("t"),
+                schema_name: String::new(),
+            })
+            .unwrap();
+        append
+            .append_rows(proto::AppendRows {
+                kind: Some(proto::append_rows::Kind::Rows(proto::RowBatch {
+                    rows: vec![proto::Row {
+                        values: vec![proto::ScalarValue {
+                            kind: Some(Kind::IntValue(1)),
+                        }],
+                    }],
+                })),
+            })
+            .unwrap();
+        drop(append);
+        assert!(!gduck.in_transaction());
+        let result = gduck
+            .query_value("SELECT COUNT(*) FROM t", proto::Params::default())
+            .unwrap();
+        assert_eq!(value(result), Kind::IntValue(0));
+    }
+
+    #[test]
+    fn load_arrow_into_temporary_table() {
+        let gduck = gduck();
+        let loaded = gduck
+            .load_arrow(proto::query::LoadArrow {
+                table_name: String::from("t"),
+                arrow_ipc: arrow_ipc(&[dictionary_batch(0, 3000)]),
+                create_temporary: true,
+                replace: false,
+            })
+            .unwrap();
+        assert_eq!(
+            loaded.kind,
+            Some(proto::response::query_result::Kind::Appended(
+                proto::Appended { num_rows: 3000 }
+            ))
+        );
+        let result = gduck
+            .query_value(
+                "SELECT any_value(typeof(s)) || ' ' || COUNT(DISTINCT s) FROM t",
+                proto::Params::default(),
+            )
+            .unwrap();
+        assert_eq!(value(result), Kind::StrValue(String::from("VARCHAR 3")));
+    }
+
+    #[test]
+    fn run_prepared_statements() {
+        let gduck = gduck();
+        let mut with_params = gduck.prepare("SELECT $a * $b").unwrap();
+        assert_eq!(
+            with_params.prepared(1).kind,
+            Some(proto::response::query_result::Kind::Prepared(
+                proto::Prepared {
+                    handle: 1,
+                    parameter_count: 2,
+                    schema: None,
+                }
+            ))
+        );
+        let executed = results(|sink| {
+            with_params.run(
+                proto::query::ExecutePrepared {
+                    handle: 1,
+                    params: Some(named(&[("a", Kind::IntValue(2)), ("b", Kind::IntValue(3))])),
+                    output: proto::query::execute_prepared::Output::Value.into(),
+                    batch_size: 0,
+                },
+                sink,
+            )
+        });
+        assert_eq!(
+            executed.unwrap(),
+            vec![proto::response::query_result::Kind::Value(
+                proto::ScalarValue {
+                    kind: Some(Kind::IntValue(6))
+                }
+            )]
+        );
+
+        let without_params = gduck.prepare("SELECT 42 AS answer").unwrap();
+        match without_params.prepared(2).kind {
+            Some(proto::response::query_result::Kind::Prepared(proto::Prepared {
+                schema: Some(schema),
+                ..
+            })) => assert_eq!(schema.columns[0].name, "answer"),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        gduck
+            .execute("CREATE TABLE p (a INTEGER, b VARCHAR)", Default::default())
+            .unwrap();
+        let filtered = gduck.prepare("SELECT * FROM p WHERE a = ?").unwrap();
+        match filtered.prepared(2).kind {
+            Some(proto::response::query_result::Kind::Prepared(proto::Prepared {
+                parameter_count: 1,
+                schema: Some(schema),
+                ..
+            })) => assert_eq!(
+                schema
+                    .columns
+                    .iter()
+                    .map(|column| column.name.as_str())
+                    .collect::<Vec<_>>(),
+                vec!["a", "b"]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+        // The query is described without being run.
+        let started = std::time::Instant::now();
+        let counted = gduck
+            .prepare("SELECT count(*) AS \"n\"\"s\" FROM range(1000000000000) WHERE random() < 0")
+            .unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        match counted.prepared(2).kind {
+            Some(proto::response::query_result::Kind::Prepared(proto::Prepared {
+                schema: Some(schema),
+                ..
+            })) => assert_eq!(schema.columns[0].name, "n\"s"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        let untyped = gduck.prepare("SELECT ? AS x").unwrap();
+        assert!(matches!(
+            untyped.prepared(2).kind,
+            Some(proto::response::query_result::Kind::Prepared(
+                proto::Prepared { schema: None, .. }
+            ))
+        ));
+
+        let mut create = gduck.prepare("CREATE TABLE t (i INTEGER)").unwrap();
+        assert!(matches!(
+            create.prepared(3).kind,
+            Some(proto::response::query_result::Kind::Prepared(
+                proto::Prepared { schema: None, .. }
+            ))
+        ));
+        let executed = results(|sink| {
+            create.run(
+                proto::query::ExecutePrepared {
+                    handle: 3,
+                    ..Default::default()
+                },
+                sink,
+            )
+        });
+        assert_eq!(
+            executed.unwrap(),
+            vec![proto::response::query_result::Kind::Ok(())]
+        );
+    }
+
+    #[test]
+    fn export_to_local_file() {
+        let gduck = gduck();
+        let path = std::env::temp_dir().join(format!("gduck-export-{}.csv", std::process::id()));
+        let exported = gduck
+            .export(
+                "SELECT * FROM range(10)",
+                proto::Params::default(),
+                Uri::LocalFileSystem(path.clone()),
+                Some(proto::query::export_query::Format::Csv(
+                    proto::query::export_query::Csv::default(),
+                )),
+            )
+            .unwrap();
+        let written = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written.unwrap().lines().count(), 11);
+        #[allow(deprecated)]
+        let parquet_file = exported.parquet_file;
+        // Only exports to Parquet set it.
+        assert_eq!(parquet_file, None);
+        assert_eq!(
+            exported.kind,
+            Some(proto::response::query_result::Kind::Exported(
+                proto::Exported {
+                    location: Some(proto::Location::try_from(Uri::LocalFileSystem(path)).unwrap()),
+                    num_rows: 10,
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn reject_export_to_azure() {
+        let gduck = gduck();
+        let uri = Uri::Azure {
+            account_name: String::from("account"),
+            container: String::from("container"),
+            path: String::from("exported.parquet"),
+            data_lake: false,
+            credential: String::new(),
+        };
+        assert!(matches!(
+            gduck.export("SELECT 1", proto::Params::default(), uri, None),
+            Err(Error::UnsupportedUri(_))
+        ));
     }
 }
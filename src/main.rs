@@ -1,8 +1,11 @@
+mod database;
 mod error;
 mod gduck;
 mod proto;
 mod service;
+mod session;
 mod uri;
+mod value;
 
 use std::net::SocketAddr;
 
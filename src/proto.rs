@@ -1,3 +1,5 @@
+#![allow(clippy::enum_variant_names, clippy::large_enum_variant)]
+
 use chrono::{Datelike, Timelike};
 
 tonic::include_proto!("gduck");
@@ -12,13 +14,118 @@ impl From<connect::Mode> for duckdb::AccessMode {
     }
 }
 
+impl From<&crate::error::Error> for ErrorCode {
+    fn from(value: &crate::error::Error) -> Self {
+        match value {
+            crate::error::Error::ProtocolError { .. } => ErrorCode::Protocol,
+            crate::error::Error::DatabaseError { kind, .. } => match kind {
+                crate::error::DatabaseErrorKind::Unknown => ErrorCode::Database,
+                crate::error::DatabaseErrorKind::Parser => ErrorCode::Parser,
+                crate::error::DatabaseErrorKind::Binder => ErrorCode::Binder,
+                crate::error::DatabaseErrorKind::Catalog => ErrorCode::Catalog,
+                crate::error::DatabaseErrorKind::Constraint => ErrorCode::Constraint,
+                crate::error::DatabaseErrorKind::Conversion => ErrorCode::Conversion,
+                crate::error::DatabaseErrorKind::OutOfMemory => ErrorCode::OutOfMemory,
+                crate::error::DatabaseErrorKind::Io => ErrorCode::Io,
+                crate::error::DatabaseErrorKind::InvalidInput => ErrorCode::InvalidInput,
+                crate::error::DatabaseErrorKind::Transaction => ErrorCode::Transaction,
+                crate::error::DatabaseErrorKind::Permission => ErrorCode::Permission,
+                crate::error::DatabaseErrorKind::Interrupted => ErrorCode::Interrupted,
+                crate::error::DatabaseErrorKind::NotImplemented => ErrorCode::NotImplemented,
+            },
+            crate::error::Error::QueryError { .. } => ErrorCode::Query,
+            crate::error::Error::UnsupportedTypeError { .. } => ErrorCode::UnsupportedType,
+            crate::error::Error::UnsupportedUri(_) => ErrorCode::UnsupportedLocation,
+            crate::error::Error::InvalidRequest(_) => ErrorCode::InvalidRequest,
+            crate::error::Error::InternalError { .. } => ErrorCode::Internal,
+            crate::error::Error::Cancelled => ErrorCode::Cancelled,
+            crate::error::Error::DeadlineExceeded => ErrorCode::DeadlineExceeded,
+        }
+    }
+}
+
+impl From<crate::error::Error> for Error {
+    fn from(value: crate::error::Error) -> Self {
+        Error {
+            code: ErrorCode::from(&value) as i32,
+            message: value.to_string(),
+        }
+    }
+}
+
+impl Connect {
+    /// DuckDB settings the options give by name, where the typed options take precedence
+    /// over the same ones in `settings`.
+    pub fn duckdb_settings(&self) -> std::collections::BTreeMap<String, String> {
+        let mut settings = self
+            .settings
+            .iter()
+            .map(|(key, setting)| (key.to_owned(), setting.to_owned()))
+            .collect::<std::collections::BTreeMap<String, String>>();
+        if self.threads > 0 {
+            settings.insert(String::from("threads"), self.threads.to_string());
+        }
+        if !self.memory_limit.is_empty() {
+            settings.insert(String::from("memory_limit"), self.memory_limit.clone());
+        }
+        if !self.temp_directory.is_empty() {
+            settings.insert(String::from("temp_directory"), self.temp_directory.clone());
+        }
+        if !self.max_temp_directory_size.is_empty() {
+            settings.insert(
+                String::from("max_temp_directory_size"),
+                self.max_temp_directory_size.clone(),
+            );
+        }
+        match self.default_order() {
+            connect::Order::Unspecified => {}
+            connect::Order::Asc => {
+                settings.insert(String::from("default_order"), String::from("ASC"));
+            }
+            connect::Order::Desc => {
+                settings.insert(String::from("default_order"), String::from("DESC"));
+            }
+        }
+        if let Some(enabled) = self.enable_external_access {
+            settings.insert(String::from("enable_external_access"), enabled.to_string());
+        }
+        if let Some(enabled) = self.preserve_insertion_order {
+            settings.insert(
+                String::from("preserve_insertion_order"),
+                enabled.to_string(),
+            );
+        }
+        settings
+    }
+}
+
 impl TryFrom<Connect> for duckdb::Config {
     type Error = crate::error::Error;
 
     fn try_from(value: Connect) -> crate::error::Result<Self> {
-        duckdb::Config::default()
+        fn set(
+            config: duckdb::Config,
+            key: &str,
+            value: &str,
+        ) -> crate::error::Result<duckdb::Config> {
+            config.with(key, value).map_err(|_| {
+                crate::error::Error::InvalidRequest(format!(
+                    "cannot set DuckDB setting {} to {:?}",
+                    key, value
+                ))
+            })
+        }
+
+        let mut config = duckdb::Config::default()
             .access_mode(value.mode().into())
-            .map_err(crate::error::Error::from)
+            .map_err(crate::error::Error::from)?;
+        // Tells DuckDB specific types such as HUGEINT and BIT apart in query results, see crate::value
+        config = set(config, "arrow_lossless_conversion", "true")?;
+        // Unknown settings are not detected until the database is opened.
+        for (key, setting) in value.duckdb_settings() {
+            config = set(config, &key, &setting)?;
+        }
+        Ok(config)
     }
 }
 
@@ -30,8 +137,31 @@ impl TryFrom<Location> for crate::uri::Uri {
             Some(location::Kind::Local(local)) => Ok(crate::uri::Uri::LocalFileSystem(
                 std::path::PathBuf::from(local.path),
             )),
+            Some(location::Kind::S3(object)) => Ok(crate::uri::Uri::S3 {
+                bucket: object.bucket,
+                key: object.key,
+                options: crate::uri::S3Options {
+                    region: object.region,
+                    endpoint: object.endpoint,
+                    path_style: object.path_style,
+                    use_ssl: !object.disable_ssl,
+                    credential: object.credential,
+                },
+            }),
+            Some(location::Kind::Gcs(object)) => Ok(crate::uri::Uri::Gcs {
+                bucket: object.bucket,
+                key: object.key,
+                credential: object.credential,
+            }),
+            Some(location::Kind::Azure(blob)) => Ok(crate::uri::Uri::Azure {
+                account_name: blob.account_name,
+                container: blob.container,
+                path: blob.path,
+                data_lake: blob.data_lake,
+                credential: blob.credential,
+            }),
             None => Err(crate::error::Error::ProtocolError {
-                message: String::from("Parquet file location is required."),
+                message: String::from("Location is required."),
             }),
         }
     }
@@ -47,8 +177,80 @@ impl TryFrom<crate::uri::Uri> for Location {
                     path: String::from(path.to_string_lossy()),
                 })),
             }),
-            _ => Err(crate::error::Error::UnsupportedParquetUri(uri.to_string())),
+            crate::uri::Uri::S3 {
+                bucket,
+                key,
+                options,
+            } => Ok(Location {
+                kind: Some(location::Kind::S3(location::S3Object {
+                    bucket,
+                    key,
+                    region: options.region,
+                    endpoint: options.endpoint,
+                    path_style: options.path_style,
+                    disable_ssl: !options.use_ssl,
+                    credential: options.credential,
+                })),
+            }),
+            crate::uri::Uri::Gcs {
+                bucket,
+                key,
+                credential,
+            } => Ok(Location {
+                kind: Some(location::Kind::Gcs(location::GcsObject {
+                    bucket,
+                    key,
+                    credential,
+                })),
+            }),
+            crate::uri::Uri::Azure {
+                account_name,
+                container,
+                path,
+                data_lake,
+                credential,
+            } => Ok(Location {
+                kind: Some(location::Kind::Azure(location::AzureBlob {
+                    account_name,
+                    container,
+                    path,
+                    data_lake,
+                    credential,
+                })),
+            }),
+            _ => Err(crate::error::Error::UnsupportedUri(uri.to_string())),
+        }
+    }
+}
+
+impl query::export_query::Format {
+    /// Options of `COPY ... TO` writing in the format.
+    pub fn copy_options(&self) -> String {
+        let literal = crate::uri::literal;
+        let mut options = Vec::new();
+        match self {
+            query::export_query::Format::Parquet(_) => options.push(String::from("FORMAT PARQUET")),
+            query::export_query::Format::Csv(csv) => {
+                options.push(String::from("FORMAT CSV"));
+                if !csv.delimiter.is_empty() {
+                    options.push(format!("DELIMITER {}", literal(&csv.delimiter)));
+                }
+                if let Some(header) = csv.header {
+                    options.push(format!("HEADER {}", header));
+                }
+                if !csv.quote.is_empty() {
+                    options.push(format!("QUOTE {}", literal(&csv.quote)));
+                }
+                if !csv.null_string.is_empty() {
+                    options.push(format!("NULLSTR {}", literal(&csv.null_string)));
+                }
+            }
+            query::export_query::Format::Json(json) => {
+                options.push(String::from("FORMAT JSON"));
+                options.push(format!("ARRAY {}", json.array));
+            }
         }
+        options.join(", ")
     }
 }
 
@@ -72,10 +274,20 @@ impl TryFrom<duckdb::types::Type> for DataType {
             }
             duckdb::types::Type::Decimal => Ok(DataType::DatatypeDecimal),
             duckdb::types::Type::Text => Ok(DataType::DatatypeString),
+            // duckdb-rs does not tell TIMESTAMP WITH TIME ZONE from TIMESTAMP nor know TIMETZ,
+            // so schemas of results are read from their Arrow fields instead.
             duckdb::types::Type::Timestamp => Ok(DataType::DatatypeDatetime),
             duckdb::types::Type::Date32 => Ok(DataType::DatatypeDate),
             duckdb::types::Type::Time64 => Ok(DataType::DatatypeTime),
             duckdb::types::Type::Interval => Ok(DataType::DatatypeInterval),
+            duckdb::types::Type::HugeInt => Ok(DataType::DatatypeHugeint),
+            duckdb::types::Type::Blob => Ok(DataType::DatatypeBlob),
+            duckdb::types::Type::List(_) => Ok(DataType::DatatypeList),
+            duckdb::types::Type::Array(_, _) => Ok(DataType::DatatypeArray),
+            duckdb::types::Type::Struct(_) => Ok(DataType::DatatypeStruct),
+            duckdb::types::Type::Map(_, _) => Ok(DataType::DatatypeMap),
+            duckdb::types::Type::Enum => Ok(DataType::DatatypeEnum),
+            duckdb::types::Type::Union => Ok(DataType::DatatypeUnion),
             other_type => Err(crate::error::Error::unsupported_type(other_type)),
         }
     }
@@ -104,17 +316,276 @@ impl TryFrom<duckdb::arrow::datatypes::DataType> for DataType {
             duckdb::arrow::datatypes::DataType::Utf8
             | duckdb::arrow::datatypes::DataType::Utf8View
             | duckdb::arrow::datatypes::DataType::LargeUtf8 => Ok(DataType::DatatypeString),
-            duckdb::arrow::datatypes::DataType::Timestamp(_, _) => Ok(DataType::DatatypeDatetime),
+            duckdb::arrow::datatypes::DataType::Binary
+            | duckdb::arrow::datatypes::DataType::LargeBinary
+            | duckdb::arrow::datatypes::DataType::BinaryView
+            | duckdb::arrow::datatypes::DataType::FixedSizeBinary(_) => Ok(DataType::DatatypeBlob),
+            duckdb::arrow::datatypes::DataType::Timestamp(_, None) => {
+                Ok(DataType::DatatypeDatetime)
+            }
+            duckdb::arrow::datatypes::DataType::Timestamp(_, Some(_)) => {
+                Ok(DataType::DatatypeTimestampTz)
+            }
             duckdb::arrow::datatypes::DataType::Date32 => Ok(DataType::DatatypeDate),
             duckdb::arrow::datatypes::DataType::Time64(_) => Ok(DataType::DatatypeTime),
             duckdb::arrow::datatypes::DataType::Interval(_) => Ok(DataType::DatatypeInterval),
-            t => Err(crate::error::Error::UnsupportedTypeError {
-                t: duckdb::types::Type::from(&t),
+            duckdb::arrow::datatypes::DataType::List(_)
+            | duckdb::arrow::datatypes::DataType::LargeList(_)
+            | duckdb::arrow::datatypes::DataType::ListView(_)
+            | duckdb::arrow::datatypes::DataType::LargeListView(_) => Ok(DataType::DatatypeList),
+            duckdb::arrow::datatypes::DataType::FixedSizeList(_, _) => Ok(DataType::DatatypeArray),
+            duckdb::arrow::datatypes::DataType::Struct(_) => Ok(DataType::DatatypeStruct),
+            duckdb::arrow::datatypes::DataType::Map(_, _) => Ok(DataType::DatatypeMap),
+            // DuckDB passes only ENUM as a dictionary
+            duckdb::arrow::datatypes::DataType::Dictionary(_, _) => Ok(DataType::DatatypeEnum),
+            duckdb::arrow::datatypes::DataType::Union(_, _) => Ok(DataType::DatatypeUnion),
+            t => Err(crate::error::Error::unsupported_type(t)),
+        }
+    }
+}
+
+impl TryFrom<&duckdb::arrow::datatypes::Field> for DataType {
+    type Error = crate::error::Error;
+
+    fn try_from(field: &duckdb::arrow::datatypes::Field) -> Result<Self, Self::Error> {
+        match crate::value::extension_type(field) {
+            Some("arrow.bool8") => Ok(DataType::DatatypeBool),
+            Some("arrow.uuid") => Ok(DataType::DatatypeUuid),
+            Some("hugeint") => Ok(DataType::DatatypeHugeint),
+            Some("uhugeint") => Ok(DataType::DatatypeUhugeint),
+            Some("bit") => Ok(DataType::DatatypeBit),
+            Some("time_tz") => Ok(DataType::DatatypeTimeTz),
+            // Read as text
+            Some("arrow.json") | Some("varint") | Some("bignum") => Ok(DataType::DatatypeString),
+            Some(other) => Err(crate::error::Error::unsupported_type(other)),
+            None => DataType::try_from(field.data_type().to_owned()),
+        }
+    }
+}
+
+impl ColumnType {
+    /// Type of the values of `field`. DuckDB passes the values of ENUMs along with rows
+    /// instead of in the schema, so they are set only when `column`, data of the field, is given.
+    pub fn from_field(
+        field: &duckdb::arrow::datatypes::Field,
+        column: Option<&dyn duckdb::arrow::array::Array>,
+    ) -> crate::error::Result<Self> {
+        use duckdb::arrow::array::AsArray;
+        use duckdb::arrow::datatypes::DataType as ArrowType;
+
+        let mut column_type = ColumnType {
+            data_type: DataType::try_from(field)? as i32,
+            ..Default::default()
+        };
+        match (crate::value::extension_type(field), field.data_type()) {
+            (Some("hugeint"), _) => (column_type.bit_width, column_type.signed) = (128, true),
+            (Some("uhugeint"), _) => column_type.bit_width = 128,
+            (Some("time_tz"), _) => column_type.set_time_unit(TimeUnit::Microsecond),
+            (Some(_), _) => {}
+            (None, ArrowType::Int8) => (column_type.bit_width, column_type.signed) = (8, true),
+            (None, ArrowType::Int16) => (column_type.bit_width, column_type.signed) = (16, true),
+            (None, ArrowType::Int32) => (column_type.bit_width, column_type.signed) = (32, true),
+            (None, ArrowType::Int64) => (column_type.bit_width, column_type.signed) = (64, true),
+            (None, ArrowType::UInt8) => column_type.bit_width = 8,
+            (None, ArrowType::UInt16) => column_type.bit_width = 16,
+            (None, ArrowType::UInt32) => column_type.bit_width = 32,
+            (None, ArrowType::UInt64) => column_type.bit_width = 64,
+            (None, ArrowType::Float16) => column_type.bit_width = 16,
+            (None, ArrowType::Float32) => column_type.bit_width = 32,
+            (None, ArrowType::Float64) => column_type.bit_width = 64,
+            (None, ArrowType::Decimal128(precision, scale))
+            | (None, ArrowType::Decimal256(precision, scale)) => {
+                column_type.precision = u32::from(*precision);
+                column_type.scale = u32::try_from(*scale)
+                    .map_err(|_| crate::error::Error::unsupported_type(field.data_type()))?;
+            }
+            (None, ArrowType::Timestamp(unit, time_zone)) => {
+                column_type.set_time_unit(TimeUnit::from(unit));
+                column_type.time_zone = time_zone.as_deref().unwrap_or_default().to_owned();
+            }
+            (None, ArrowType::Time32(unit)) | (None, ArrowType::Time64(unit)) => {
+                column_type.set_time_unit(TimeUnit::from(unit))
+            }
+            (None, ArrowType::List(element)) => {
+                let values = column.map(|column| column.as_list::<i32>().values().as_ref());
+                column_type.element = Some(Box::new(ColumnType::from_field(element, values)?));
+            }
+            (None, ArrowType::LargeList(element)) => {
+                let values = column.map(|column| column.as_list::<i64>().values().as_ref());
+                column_type.element = Some(Box::new(ColumnType::from_field(element, values)?));
+            }
+            (None, ArrowType::ListView(element)) | (None, ArrowType::LargeListView(element)) => {
+                column_type.element = Some(Box::new(ColumnType::from_field(element, None)?));
+            }
+            (None, ArrowType::FixedSizeList(element, size)) => {
+                let values = column.map(|column| column.as_fixed_size_list().values().as_ref());
+                column_type.element = Some(Box::new(ColumnType::from_field(element, values)?));
+                column_type.array_size = u32::try_from(*size)
+                    .map_err(|_| crate::error::Error::internal("invalid size of ARRAY"))?;
+            }
+            (None, ArrowType::Struct(fields)) => {
+                column_type.fields = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| {
+                        let values = column.map(|column| column.as_struct().column(i).as_ref());
+                        column_type::Field::from_field(field, values)
+                    })
+                    .collect::<crate::error::Result<Vec<column_type::Field>>>()?;
+            }
+            (None, ArrowType::Union(fields, _)) => {
+                column_type.fields = fields
+                    .iter()
+                    .map(|(type_id, field)| {
+                        let values = column.map(|column| column.as_union().child(type_id).as_ref());
+                        column_type::Field::from_field(field, values)
+                    })
+                    .collect::<crate::error::Result<Vec<column_type::Field>>>()?;
+            }
+            (None, ArrowType::Map(entries, _)) => match entries.data_type() {
+                ArrowType::Struct(fields) if fields.len() == 2 => {
+                    let entries = column.map(|column| column.as_map().entries());
+                    column_type.key = Some(Box::new(ColumnType::from_field(
+                        &fields[0],
+                        entries.map(|entries| entries.column(0).as_ref()),
+                    )?));
+                    column_type.value = Some(Box::new(ColumnType::from_field(
+                        &fields[1],
+                        entries.map(|entries| entries.column(1).as_ref()),
+                    )?));
+                }
+                other => return Err(crate::error::Error::unsupported_type(other)),
+            },
+            (None, ArrowType::Dictionary(_, _)) => {
+                if let Some(values) = column
+                    .and_then(|column| column.as_any_dictionary().values().as_string_opt::<i32>())
+                {
+                    column_type.enum_values = values.iter().flatten().map(String::from).collect();
+                }
+            }
+            (None, _) => {}
+        }
+        column_type.type_name = column_type.duckdb_type_name(field);
+        Ok(column_type)
+    }
+
+    // Built from the names of the element types, which are set before.
+    fn duckdb_type_name(&self, field: &duckdb::arrow::datatypes::Field) -> String {
+        use duckdb::arrow::datatypes::DataType as ArrowType;
+
+        fn element_name(element: Option<&ColumnType>) -> &str {
+            element.map_or("NULL", |element| element.type_name.as_str())
+        }
+
+        fn members(fields: &[column_type::Field]) -> String {
+            fields
+                .iter()
+                .map(|field| {
+                    format!(
+                        "\"{}\" {}",
+                        field.name.replace('"', "\"\""),
+                        element_name(field.r#type.as_ref())
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join(", ")
+        }
+
+        match (crate::value::extension_type(field), field.data_type()) {
+            (Some("arrow.bool8"), _) => String::from("BOOLEAN"),
+            (Some("arrow.uuid"), _) => String::from("UUID"),
+            (Some("time_tz"), _) => String::from("TIME WITH TIME ZONE"),
+            (Some("arrow.json"), _) => String::from("JSON"),
+            (Some(name), _) => name.to_uppercase(),
+            (None, ArrowType::Null) => String::from("NULL"),
+            (None, ArrowType::Boolean) => String::from("BOOLEAN"),
+            (None, ArrowType::Int8) => String::from("TINYINT"),
+            (None, ArrowType::Int16) => String::from("SMALLINT"),
+            (None, ArrowType::Int32) => String::from("INTEGER"),
+            (None, ArrowType::Int64) => String::from("BIGINT"),
+            (None, ArrowType::UInt8) => String::from("UTINYINT"),
+            (None, ArrowType::UInt16) => String::from("USMALLINT"),
+            (None, ArrowType::UInt32) => String::from("UINTEGER"),
+            (None, ArrowType::UInt64) => String::from("UBIGINT"),
+            (None, ArrowType::Float16) | (None, ArrowType::Float32) => String::from("FLOAT"),
+            (None, ArrowType::Float64) => String::from("DOUBLE"),
+            (None, ArrowType::Decimal128(..)) | (None, ArrowType::Decimal256(..)) => {
+                format!("DECIMAL({},{})", self.precision, self.scale)
+            }
+            (None, ArrowType::Utf8)
+            | (None, ArrowType::LargeUtf8)
+            | (None, ArrowType::Utf8View) => String::from("VARCHAR"),
+            (None, ArrowType::Binary)
+            | (None, ArrowType::LargeBinary)
+            | (None, ArrowType::BinaryView)
+            | (None, ArrowType::FixedSizeBinary(_)) => String::from("BLOB"),
+            (None, ArrowType::Date32) | (None, ArrowType::Date64) => String::from("DATE"),
+            (None, ArrowType::Time32(_)) | (None, ArrowType::Time64(_)) => String::from("TIME"),
+            (None, ArrowType::Timestamp(_, Some(_))) => String::from("TIMESTAMP WITH TIME ZONE"),
+            (None, ArrowType::Timestamp(unit, None)) => String::from(match unit {
+                duckdb::arrow::datatypes::TimeUnit::Second => "TIMESTAMP_S",
+                duckdb::arrow::datatypes::TimeUnit::Millisecond => "TIMESTAMP_MS",
+                duckdb::arrow::datatypes::TimeUnit::Microsecond => "TIMESTAMP",
+                duckdb::arrow::datatypes::TimeUnit::Nanosecond => "TIMESTAMP_NS",
             }),
+            (None, ArrowType::Interval(_)) => String::from("INTERVAL"),
+            (None, ArrowType::List(_))
+            | (None, ArrowType::LargeList(_))
+            | (None, ArrowType::ListView(_))
+            | (None, ArrowType::LargeListView(_)) => {
+                format!("{}[]", element_name(self.element.as_deref()))
+            }
+            (None, ArrowType::FixedSizeList(..)) => format!(
+                "{}[{}]",
+                element_name(self.element.as_deref()),
+                self.array_size
+            ),
+            (None, ArrowType::Struct(_)) => format!("STRUCT({})", members(&self.fields)),
+            (None, ArrowType::Union(..)) => format!("UNION({})", members(&self.fields)),
+            (None, ArrowType::Map(..)) => format!(
+                "MAP({}, {})",
+                element_name(self.key.as_deref()),
+                element_name(self.value.as_deref())
+            ),
+            (None, ArrowType::Dictionary(..)) if self.enum_values.is_empty() => {
+                String::from("ENUM")
+            }
+            (None, ArrowType::Dictionary(..)) => format!(
+                "ENUM({})",
+                self.enum_values
+                    .iter()
+                    .map(|value| format!("'{}'", value.replace('\'', "''")))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            (None, other) => other.to_string(),
         }
     }
 }
 
+impl From<&duckdb::arrow::datatypes::TimeUnit> for TimeUnit {
+    fn from(value: &duckdb::arrow::datatypes::TimeUnit) -> Self {
+        match value {
+            duckdb::arrow::datatypes::TimeUnit::Second => TimeUnit::Second,
+            duckdb::arrow::datatypes::TimeUnit::Millisecond => TimeUnit::Millisecond,
+            duckdb::arrow::datatypes::TimeUnit::Microsecond => TimeUnit::Microsecond,
+            duckdb::arrow::datatypes::TimeUnit::Nanosecond => TimeUnit::Nanosecond,
+        }
+    }
+}
+
+impl column_type::Field {
+    fn from_field(
+        field: &duckdb::arrow::datatypes::Field,
+        column: Option<&dyn duckdb::arrow::array::Array>,
+    ) -> crate::error::Result<Self> {
+        Ok(column_type::Field {
+            name: field.name().to_owned(),
+            r#type: Some(ColumnType::from_field(field, column)?),
+        })
+    }
+}
+
 impl duckdb::types::ToSql for scalar_value::Kind {
     fn to_sql(&self) -> duckdb::Result<duckdb::types::ToSqlOutput<'_>> {
         match self {
@@ -125,160 +596,472 @@ impl duckdb::types::ToSql for scalar_value::Kind {
             scalar_value::Kind::IntValue(i) => Ok(duckdb::types::ToSqlOutput::from(*i)),
             scalar_value::Kind::UintValue(u) => Ok(duckdb::types::ToSqlOutput::from(*u)),
             scalar_value::Kind::DoubleValue(d) => Ok(duckdb::types::ToSqlOutput::from(*d)),
-            scalar_value::Kind::DecimalValue(d) => {
-                Ok(duckdb::types::ToSqlOutput::from(d.value.to_owned()))
+            scalar_value::Kind::DecimalValue(d) => d
+                .checked()
+                .and_then(|d| d.to_duckdb())
+                .map(|d| duckdb::types::ToSqlOutput::Owned(duckdb::types::Value::Decimal(d)))
+                .map_err(|err| duckdb::Error::ToSqlConversionFailure(Box::new(err))),
+            scalar_value::Kind::StrValue(s) | scalar_value::Kind::UuidValue(s) => {
+                Ok(duckdb::types::ToSqlOutput::from(s.to_owned()))
+            }
+            scalar_value::Kind::BytesValue(bytes) => Ok(duckdb::types::ToSqlOutput::Borrowed(
+                duckdb::types::ValueRef::Blob(bytes),
+            )),
+            scalar_value::Kind::DatetimeValue(dt) if dt.nanos % 1_000 == 0 => timestamp_micros(dt)
+                .map(|micros| {
+                    duckdb::types::ToSqlOutput::Borrowed(duckdb::types::ValueRef::Timestamp(
+                        duckdb::types::TimeUnit::Microsecond,
+                        micros,
+                    ))
+                }),
+            // duckdb-rs binds timestamps in microseconds, so finer ones are bound as text
+            // to be cast by DuckDB, e.g. to TIMESTAMP_NS.
+            scalar_value::Kind::DatetimeValue(dt) => {
+                timestamp_text(dt).map(duckdb::types::ToSqlOutput::from)
+            }
+            // duckdb-rs can bind only TIMESTAMP, which DuckDB would read in the time zone
+            // of the session, so they are bound as text with the offset to be cast by DuckDB.
+            scalar_value::Kind::TimestamptzValue(dt) => {
+                timestamptz_text(dt).map(duckdb::types::ToSqlOutput::from)
+            }
+            scalar_value::Kind::TimetzValue(time) => {
+                timetz_text(time).map(duckdb::types::ToSqlOutput::from)
             }
-            scalar_value::Kind::StrValue(s) => Ok(duckdb::types::ToSqlOutput::from(s.to_owned())),
-            scalar_value::Kind::DatetimeValue(dt) => u32::try_from(dt.nanos)
-                .map_err(|err| duckdb::Error::ToSqlConversionFailure(Box::new(err)))
-                .and_then(|nanos| {
-                    chrono::DateTime::from_timestamp(dt.seconds, nanos).ok_or_else(|| {
-                        duckdb::Error::ToSqlConversionFailure(Box::new(
-                            crate::error::Error::InvalidRequest(format!(
-                                "invalid timestamp {}",
-                                dt
-                            )),
-                        ))
-                    })
-                })
-                .map(|dt| duckdb::types::ToSqlOutput::from(dt.format("%F %T%.f").to_string())), // refer to https://github.com/duckdb/duckdb-rs/blob/main/crates/duckdb/src/types/chrono.rs#L51
             scalar_value::Kind::DateValue(dt) => {
-                chrono::NaiveDate::from_ymd_opt(dt.year, dt.month, dt.day)
-                    .ok_or_else(|| {
-                        duckdb::Error::ToSqlConversionFailure(Box::new(
-                            crate::error::Error::InvalidRequest(format!(
-                                "invalid timestamp {}-{}-{}",
-                                dt.year, dt.month, dt.day
-                            )),
-                        ))
-                    })
-                    .map(|dt| duckdb::types::ToSqlOutput::from(dt.format("%F").to_string()))
-                // refer to https://github.com/duckdb/duckdb-rs/blob/main/crates/duckdb/src/types/chrono.rs#L17
+                date_text(dt).map(duckdb::types::ToSqlOutput::from)
             }
-            scalar_value::Kind::TimeValue(time) => chrono::NaiveTime::from_hms_nano_opt(
-                time.hours,
-                time.minutes,
-                time.seconds,
-                time.nanos,
-            )
-            .ok_or_else(|| {
-                duckdb::Error::ToSqlConversionFailure(Box::new(
-                    crate::error::Error::InvalidRequest(format!(
-                        "invalid timestamp {}:{}:{}.{}",
-                        time.hours, time.minutes, time.seconds, time.nanos
-                    )),
-                ))
-            })
-            .map(|time| duckdb::types::ToSqlOutput::from(time.format("%T%.f").to_string())), // refer to https://github.com/duckdb/duckdb-rs/blob/main/crates/duckdb/src/types/chrono.rs#L34
-            scalar_value::Kind::IntervalValue(interval) => Ok(
+            scalar_value::Kind::TimeValue(time) => {
+                time_text(time).map(duckdb::types::ToSqlOutput::from)
+            }
+            scalar_value::Kind::HugeintValue(hugeint) => Ok(duckdb::types::ToSqlOutput::Owned(
+                duckdb::types::Value::HugeInt(i128::from(hugeint)),
+            )),
+            // duckdb-rs cannot bind UHUGEINT, so it is bound as a string to be cast by DuckDB.
+            scalar_value::Kind::UhugeintValue(uhugeint) => Ok(duckdb::types::ToSqlOutput::from(
+                u128::from(uhugeint).to_string(),
+            )),
+            scalar_value::Kind::IntervalValue(interval) => interval_micros(interval).map(|_| {
                 duckdb::types::ToSqlOutput::Borrowed(duckdb::types::ValueRef::Interval {
                     months: interval.months,
                     days: interval.days,
                     nanos: interval.nanos,
-                }),
-            ),
+                })
+            }),
+            // duckdb-rs cannot bind nested values, so their leaves are bound instead.
+            // See `gduck::expand_nested_params`.
+            scalar_value::Kind::ListValue(_)
+            | scalar_value::Kind::StructValue(_)
+            | scalar_value::Kind::MapValue(_)
+            | scalar_value::Kind::ArrayValue(_) => {
+                Err(duckdb::Error::ToSqlConversionFailure(Box::new(
+                    crate::error::Error::internal("nested param is bound without being expanded"),
+                )))
+            }
+            scalar_value::Kind::UnionValue(union) => {
+                match union.value.as_ref().and_then(|value| value.kind.as_ref()) {
+                    Some(kind) => kind.to_sql(),
+                    None => Err(duckdb::Error::ToSqlConversionFailure(Box::new(
+                        crate::error::Error::InvalidRequest(String::from(
+                            "value of union param is missing",
+                        )),
+                    ))),
+                }
+            }
         }
     }
 }
 
-impl TryInto<duckdb::ParamsFromIter<Vec<scalar_value::Kind>>> for Params {
+fn invalid_timestamp(dt: &prost_types::Timestamp) -> duckdb::Error {
+    duckdb::Error::ToSqlConversionFailure(Box::new(crate::error::Error::InvalidRequest(format!(
+        "invalid timestamp {}",
+        dt
+    ))))
+}
+
+fn timestamp_micros(dt: &prost_types::Timestamp) -> duckdb::Result<i64> {
+    if !(0..1_000_000_000).contains(&dt.nanos) {
+        return Err(invalid_timestamp(dt));
+    }
+    dt.seconds
+        .checked_mul(1_000_000)
+        .and_then(|micros| micros.checked_add(i64::from(dt.nanos / 1_000)))
+        .ok_or_else(|| invalid_timestamp(dt))
+}
+
+// refer to https://github.com/duckdb/duckdb-rs/blob/main/crates/duckdb/src/types/chrono.rs#L51
+fn timestamp_text(dt: &prost_types::Timestamp) -> duckdb::Result<String> {
+    u32::try_from(dt.nanos)
+        .ok()
+        .and_then(|nanos| chrono::DateTime::from_timestamp(dt.seconds, nanos))
+        .ok_or_else(|| invalid_timestamp(dt))
+        .map(|dt| dt.format("%F %T%.f").to_string())
+}
+
+fn timestamptz_text(dt: &prost_types::Timestamp) -> duckdb::Result<String> {
+    timestamp_text(dt).map(|text| format!("{}+00", text))
+}
+
+// INTERVAL holds microseconds, so finer intervals are rejected instead of being truncated.
+fn interval_micros(interval: &Interval) -> duckdb::Result<i64> {
+    if interval.nanos % 1_000 != 0 {
+        return Err(duckdb::Error::ToSqlConversionFailure(Box::new(
+            crate::error::Error::InvalidRequest(format!(
+                "interval of {} nanoseconds is finer than microseconds",
+                interval.nanos
+            )),
+        )));
+    }
+    Ok(interval.nanos / 1_000)
+}
+
+// refer to https://github.com/duckdb/duckdb-rs/blob/main/crates/duckdb/src/types/chrono.rs#L17
+fn date_text(dt: &Date) -> duckdb::Result<String> {
+    chrono::NaiveDate::from_ymd_opt(dt.year, dt.month, dt.day)
+        .ok_or_else(|| {
+            duckdb::Error::ToSqlConversionFailure(Box::new(crate::error::Error::InvalidRequest(
+                format!("invalid timestamp {}-{}-{}", dt.year, dt.month, dt.day),
+            )))
+        })
+        .map(|dt| dt.format("%F").to_string())
+}
+
+// refer to https://github.com/duckdb/duckdb-rs/blob/main/crates/duckdb/src/types/chrono.rs#L34
+fn time_text(time: &Time) -> duckdb::Result<String> {
+    chrono::NaiveTime::from_hms_nano_opt(time.hours, time.minutes, time.seconds, time.nanos)
+        .ok_or_else(|| {
+            duckdb::Error::ToSqlConversionFailure(Box::new(crate::error::Error::InvalidRequest(
+                format!(
+                    "invalid timestamp {}:{}:{}.{}",
+                    time.hours, time.minutes, time.seconds, time.nanos
+                ),
+            )))
+        })
+        .map(|time| time.format("%T%.f").to_string())
+}
+
+fn timetz_text(time: &TimeTz) -> duckdb::Result<String> {
+    let offset = time.offset_seconds;
+    if offset.abs() > TimeTz::MAX_OFFSET_SECONDS {
+        return Err(duckdb::Error::ToSqlConversionFailure(Box::new(
+            crate::error::Error::InvalidRequest(format!("invalid time zone offset {}", offset)),
+        )));
+    }
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.unsigned_abs();
+    let text = match time.time.as_ref() {
+        Some(time) => time_text(time)?,
+        None => String::from("00:00:00"),
+    };
+    Ok(format!(
+        "{}{}{:02}:{:02}:{:02}",
+        text,
+        sign,
+        offset / 3600,
+        offset / 60 % 60,
+        offset % 60
+    ))
+}
+
+impl TryFrom<Params> for crate::gduck::StatementParams {
     type Error = crate::error::Error;
 
-    fn try_into(self) -> Result<duckdb::ParamsFromIter<Vec<scalar_value::Kind>>, Self::Error> {
-        Ok(duckdb::params_from_iter(
-            self.params
+    fn try_from(value: Params) -> Result<Self, Self::Error> {
+        fn kind(param: ScalarValue) -> crate::error::Result<scalar_value::Kind> {
+            match param.kind {
+                Some(k) => Ok(k),
+                None => Err(crate::error::Error::InvalidRequest(format!(
+                    "Invalid param: {:?}",
+                    param
+                ))),
+            }
+        }
+
+        if value.named_params.is_empty() {
+            return Ok(crate::gduck::StatementParams::Positional(
+                value
+                    .params
+                    .into_iter()
+                    .map(kind)
+                    .collect::<crate::error::Result<Vec<scalar_value::Kind>>>()?,
+            ));
+        }
+        if !value.params.is_empty() {
+            return Err(crate::error::Error::InvalidRequest(String::from(
+                "positional and named params cannot be given together",
+            )));
+        }
+        Ok(crate::gduck::StatementParams::Named(
+            value
+                .named_params
                 .into_iter()
-                .map(|param: ScalarValue| match param.kind {
-                    Some(k) => Ok(k),
-                    None => Err(crate::error::Error::InvalidRequest(format!(
-                        "Invalid param: {:?}",
-                        param
-                    ))),
+                .map(|(name, param)| {
+                    // DuckDB looks up the placeholder by its name without `$`.
+                    let name = name.strip_prefix('$').unwrap_or(&name).to_owned();
+                    Ok((name, kind(param)?))
                 })
-                .collect::<Result<Vec<scalar_value::Kind>, crate::error::Error>>()?,
+                .collect::<crate::error::Result<Vec<(String, scalar_value::Kind)>>>()?,
         ))
     }
 }
 
-impl duckdb::types::FromSql for scalar_value::Kind {
-    fn column_result(value: duckdb::types::ValueRef<'_>) -> duckdb::types::FromSqlResult<Self> {
-        match value {
-            duckdb::types::ValueRef::Null => Ok(scalar_value::Kind::NullValue(
-                prost_types::NullValue::NullValue as i32,
-            )),
-            duckdb::types::ValueRef::Boolean(v) => Ok(scalar_value::Kind::BoolValue(v)),
-            duckdb::types::ValueRef::TinyInt(v) => Ok(scalar_value::Kind::IntValue(i64::from(v))),
-            duckdb::types::ValueRef::SmallInt(v) => Ok(scalar_value::Kind::IntValue(i64::from(v))),
-            duckdb::types::ValueRef::Int(v) => Ok(scalar_value::Kind::IntValue(i64::from(v))),
-            duckdb::types::ValueRef::BigInt(v) => Ok(scalar_value::Kind::IntValue(v)),
-            duckdb::types::ValueRef::HugeInt(v) => Err(duckdb::types::FromSqlError::OutOfRange(v)),
-            duckdb::types::ValueRef::UTinyInt(v) => Ok(scalar_value::Kind::UintValue(u64::from(v))),
-            duckdb::types::ValueRef::USmallInt(v) => {
-                Ok(scalar_value::Kind::UintValue(u64::from(v)))
-            }
-            duckdb::types::ValueRef::UInt(v) => Ok(scalar_value::Kind::UintValue(u64::from(v))),
-            duckdb::types::ValueRef::UBigInt(v) => Ok(scalar_value::Kind::UintValue(v)),
-            duckdb::types::ValueRef::Float(v) => Ok(scalar_value::Kind::DoubleValue(f64::from(v))),
-            duckdb::types::ValueRef::Double(v) => Ok(scalar_value::Kind::DoubleValue(v)),
-            duckdb::types::ValueRef::Decimal(v) => Ok(scalar_value::Kind::DecimalValue(Decimal {
-                value: v.to_string(),
-            })),
-            duckdb::types::ValueRef::Timestamp(..) => chrono::NaiveDateTime::column_result(value)
-                .and_then(|dt| {
-                    let utc = dt.and_utc();
-                    let seconds = utc.timestamp();
-                    let nanos = utc
-                        .timestamp_nanos_opt()
-                        .unwrap_or_else(|| utc.timestamp_micros() * 1000)
-                        % 1000000000;
-
-                    i32::try_from(nanos)
-                        .map_err(|_| {
-                            duckdb::types::FromSqlError::OutOfRange(i128::from(
-                                utc.timestamp_micros() * 1000,
-                            ))
-                        })
-                        .map(|nanos| prost_types::Timestamp { seconds, nanos })
-                })
-                .map(scalar_value::Kind::DatetimeValue),
-            duckdb::types::ValueRef::Date32(_) => {
-                chrono::NaiveDate::column_result(value).map(|dt| {
-                    scalar_value::Kind::DateValue(Date {
-                        year: dt.year(),
-                        month: dt.month(),
-                        day: dt.day(),
-                    })
-                })
-            }
-            duckdb::types::ValueRef::Time64(..) => {
-                chrono::NaiveTime::column_result(value).map(|time| {
-                    scalar_value::Kind::TimeValue(Time {
-                        hours: time.hour(),
-                        minutes: time.minute(),
-                        seconds: time.second(),
-                        nanos: time.nanosecond(),
-                    })
-                })
-            }
-            duckdb::types::ValueRef::Interval {
-                months,
-                days,
-                nanos,
-            } => Ok(scalar_value::Kind::IntervalValue(Interval {
-                months,
-                days,
-                nanos,
-            })),
-            duckdb::types::ValueRef::Text(v) => String::from_utf8(v.to_owned())
-                .map(scalar_value::Kind::StrValue)
-                .map_err(|err| {
-                    duckdb::types::FromSqlError::Other(Box::new(crate::error::Error::internal(
-                        err.to_string(),
-                    )))
-                }),
-            other => Err(duckdb::types::FromSqlError::Other(Box::new(
-                crate::error::Error::unsupported_type(other.data_type()),
-            ))),
+impl From<chrono::NaiveDate> for Date {
+    fn from(date: chrono::NaiveDate) -> Self {
+        Date {
+            year: date.year(),
+            month: date.month(),
+            day: date.day(),
         }
     }
 }
+
+impl From<chrono::NaiveTime> for Time {
+    fn from(time: chrono::NaiveTime) -> Self {
+        Time {
+            hours: time.hour(),
+            minutes: time.minute(),
+            seconds: time.second(),
+            nanos: time.nanosecond(),
+        }
+    }
+}
+
+impl Decimal {
+    /// Largest precision of DECIMAL in DuckDB.
+    pub const MAX_PRECISION: u32 = 38;
+
+    /// Checks that the value fits in the precision and scale, which are inferred from the value
+    /// if they are not given, and returns it with the value written in the scale.
+    fn checked(&self) -> crate::error::Result<Decimal> {
+        let invalid = |reason: String| {
+            crate::error::Error::InvalidRequest(format!(
+                "invalid decimal {:?}: {}",
+                self.value, reason
+            ))
+        };
+
+        let (sign, digits) = match self.value.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", self.value.strip_prefix('+').unwrap_or(&self.value)),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if (integer.is_empty() && fraction.is_empty())
+            || !integer
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid(String::from("not a decimal number")));
+        }
+        let integer = integer.trim_start_matches('0');
+        let fraction = fraction.trim_end_matches('0');
+
+        let (precision, scale) = match (self.precision, self.scale) {
+            (0, 0) => (
+                (integer.len() + fraction.len()).max(1) as u32,
+                fraction.len() as u32,
+            ),
+            (0, _) => return Err(invalid(String::from("scale is given without precision"))),
+            (precision, scale) => (precision, scale),
+        };
+        if precision > Self::MAX_PRECISION || scale > precision {
+            return Err(invalid(format!(
+                "DECIMAL({},{}) is not supported",
+                precision, scale
+            )));
+        }
+        if integer.len() > (precision - scale) as usize || fraction.len() > scale as usize {
+            return Err(invalid(format!(
+                "the value does not fit in DECIMAL({},{})",
+                precision, scale
+            )));
+        }
+
+        let integer = if integer.is_empty() { "0" } else { integer };
+        let value = match scale {
+            0 => format!("{}{}", sign, integer),
+            _ => format!(
+                "{}{}.{:0<width$}",
+                sign,
+                integer,
+                fraction,
+                width = scale as usize
+            ),
+        };
+        Ok(Decimal {
+            value,
+            precision,
+            scale,
+        })
+    }
+
+    /// DECIMAL of duckdb-rs, which the value is bound as. The value must be checked.
+    fn to_duckdb(&self) -> crate::error::Result<duckdb::types::Decimal> {
+        let invalid = |reason: String| {
+            crate::error::Error::InvalidRequest(format!(
+                "invalid decimal {:?}: {}",
+                self.value, reason
+            ))
+        };
+        let unscaled = self
+            .value
+            .replace('.', "")
+            .parse::<i128>()
+            .map_err(|err| invalid(err.to_string()))?;
+        duckdb::types::Decimal::new(self.precision as u8, self.scale as u8, unscaled)
+            .map_err(|err| invalid(err.to_string()))
+    }
+}
+
+impl TimeTz {
+    /// Largest offset from UTC DuckDB supports, i.e. 15:59:59.
+    pub const MAX_OFFSET_SECONDS: i32 = 16 * 60 * 60 - 1;
+}
+
+impl From<i128> for HugeInt {
+    fn from(value: i128) -> Self {
+        HugeInt {
+            upper: (value >> 64) as i64,
+            lower: value as u64,
+        }
+    }
+}
+
+impl From<&HugeInt> for i128 {
+    fn from(value: &HugeInt) -> Self {
+        (i128::from(value.upper) << 64) | i128::from(value.lower)
+    }
+}
+
+impl From<u128> for UHugeInt {
+    fn from(value: u128) -> Self {
+        UHugeInt {
+            upper: (value >> 64) as u64,
+            lower: value as u64,
+        }
+    }
+}
+
+impl From<&UHugeInt> for u128 {
+    fn from(value: &UHugeInt) -> Self {
+        (u128::from(value.upper) << 64) | u128::from(value.lower)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(value: &str, precision: u32, scale: u32) -> Decimal {
+        Decimal {
+            value: value.to_owned(),
+            precision,
+            scale,
+        }
+    }
+
+    #[test]
+    fn error_code() {
+        for (err, code) in [
+            (
+                crate::error::Error::DatabaseError {
+                    kind: crate::error::DatabaseErrorKind::from_message("Catalog Error: ..."),
+                    message: String::new(),
+                },
+                ErrorCode::Catalog,
+            ),
+            (
+                crate::error::Error::UnsupportedUri(String::from("ftp://host/a")),
+                ErrorCode::UnsupportedLocation,
+            ),
+            (
+                crate::error::Error::InvalidRequest(String::new()),
+                ErrorCode::InvalidRequest,
+            ),
+            (crate::error::Error::Cancelled, ErrorCode::Cancelled),
+        ] {
+            let message = err.to_string();
+            assert_eq!(
+                Error::from(err),
+                Error {
+                    code: code as i32,
+                    message,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn check_decimal() {
+        for (given, expected) in [
+            (decimal("12.340", 0, 0), decimal("12.34", 4, 2)),
+            (decimal("-0.5", 0, 0), decimal("-0.5", 1, 1)),
+            (decimal("+007", 0, 0), decimal("7", 1, 0)),
+            (decimal("0", 0, 0), decimal("0", 1, 0)),
+            (decimal(".25", 0, 0), decimal("0.25", 2, 2)),
+            (decimal("1.5", 10, 3), decimal("1.500", 10, 3)),
+            (
+                decimal("12345678901234567890.123456789012345678", 38, 18),
+                decimal("12345678901234567890.123456789012345678", 38, 18),
+            ),
+        ] {
+            assert_eq!(given.checked().unwrap(), expected, "{:?}", given);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_decimal() {
+        for given in [
+            decimal("", 0, 0),
+            decimal(".", 0, 0),
+            decimal("1e3", 0, 0),
+            decimal("1.2.3", 0, 0),
+            decimal("- 1", 0, 0),
+            decimal("1", 0, 2),
+            decimal("1", 39, 0),
+            decimal("1", 2, 3),
+            decimal("123.4", 4, 2),
+            decimal("1.234", 4, 2),
+        ] {
+            assert!(
+                matches!(given.checked(), Err(crate::error::Error::InvalidRequest(_))),
+                "{:?}",
+                given
+            );
+        }
+    }
+
+    #[test]
+    fn copy_options() {
+        use query::export_query::{Csv, Format, Json, Parquet};
+
+        assert_eq!(Format::Parquet(Parquet {}).copy_options(), "FORMAT PARQUET");
+        assert_eq!(Format::Csv(Csv::default()).copy_options(), "FORMAT CSV");
+        assert_eq!(
+            Format::Csv(Csv {
+                delimiter: String::from("|"),
+                header: Some(false),
+                quote: String::from("'"),
+                null_string: String::from("NULL"),
+            })
+            .copy_options(),
+            "FORMAT CSV, DELIMITER '|', HEADER false, QUOTE '''', NULLSTR 'NULL'"
+        );
+        assert_eq!(
+            Format::Json(Json { array: true }).copy_options(),
+            "FORMAT JSON, ARRAY true"
+        );
+    }
+
+    #[test]
+    fn reject_interval_finer_than_micros() {
+        let interval = |nanos| {
+            scalar_value::Kind::IntervalValue(Interval {
+                months: 1,
+                days: 2,
+                nanos,
+            })
+        };
+        assert!(duckdb::types::ToSql::to_sql(&interval(3_000)).is_ok());
+        assert!(duckdb::types::ToSql::to_sql(&interval(3_001)).is_err());
+    }
+}
@@ -1,13 +1,18 @@
 use std::boxed::Box;
+use std::collections::VecDeque;
 use std::pin::Pin;
 
 use tokio_stream::{Stream, StreamExt};
 
+use crate::database::Registry;
 use crate::proto;
 use crate::proto::db_service_server as grpc;
+use crate::session::{ResultReceiver, Session};
 
 #[derive(Debug)]
-pub struct DuckDbService {}
+pub struct DuckDbService {
+    databases: std::sync::Arc<Registry>,
+}
 
 #[tonic::async_trait]
 impl grpc::DbService for DuckDbService {
@@ -18,71 +23,233 @@ impl grpc::DbService for DuckDbService {
         &self,
         request: tonic::Request<tonic::Streaming<proto::Request>>,
     ) -> Result<tonic::Response<Self::TransactionStream>, tonic::Status> {
+        let call_deadline = grpc_timeout(request.metadata())
+            .and_then(|timeout| std::time::Instant::now().checked_add(timeout));
         let mut stream = request.into_inner();
 
-        let gduck = stream.try_next().await?.and_then(|request| {
-            request.message.map(|message| {
-                if let proto::request::Message::Connect(c) = message {
-                    crate::gduck::Gduck::connect(c)
-                } else {
-                    Err(crate::error::Error::ProtocolError {
-                        message: String::from("Transaction must begin with Connect message."),
-                    })
-                }
-            })
-        });
-
-        match gduck {
-            Some(Ok(gduck)) => {
-                let output = async_stream::stream! {
-                    while let Some(request) = stream.try_next().await? {
-                        if let Some(proto::request::Message::Query(q)) = request.message {
-                            let query_result = match q.kind {
-                                Some(proto::query::Kind::Execute(q)) => gduck.execute(q.query, q.params.unwrap_or_default()),
-                                Some(proto::query::Kind::Value(q)) => gduck.query_value(q.query, q.params.unwrap_or_default()),
-                                Some(proto::query::Kind::Rows(q)) => gduck.query_rows(q.query, q.params.unwrap_or_default()),
-                                Some(proto::query::Kind::Ctas(ctas)) => gduck.create_table_as(ctas.table_name, ctas.query, ctas.params.unwrap_or_default()),
-                                Some(proto::query::Kind::Parquet(pq)) => {
-                                    match pq.location {
-                                        Some(l) => crate::uri::Uri::try_from(l).and_then(|loc| gduck.query_as_parquet(pq.query, pq.params.unwrap_or_default(), loc)),
-                                        None => Err(crate::error::Error::InvalidRequest(String::from("Parquet file location is required.")))
-                                    }
-                                }
-                                kind => Err(crate::error::Error::ProtocolError { message: format!("Unknown query: {:?}", kind) })
-                            };
-
-                            match query_result {
-                                Ok(result) => {
-                                    yield Ok(proto::Response{ result: Some(proto::response::Result::Success(result))})
-                                },
-                                Err(err) => {
-                                    yield Err(tonic::Status::new(tonic::Code::Internal, err.to_string()));
-                                }
-                            }
-                        } else {
-                            yield Err(tonic::Status::new(tonic::Code::Internal, "Unknown type of request received."));
-                        }
+        let session = match stream.try_next().await?.and_then(|request| request.message) {
+            Some(proto::request::Message::Connect(c)) => {
+                Session::connect(c, self.databases.clone()).await?
+            }
+            Some(_) => {
+                return Err(tonic::Status::from(crate::error::Error::ProtocolError {
+                    message: String::from("Transaction must begin with Connect message."),
+                }))
+            }
+            None => return Err(tonic::Status::ok("No query was sent.")),
+        };
 
+        let output = respond(session, stream, call_deadline);
+        Ok(tonic::Response::new(
+            Box::pin(output) as Self::TransactionStream
+        ))
+    }
+}
+
+/// Runs the requests of a transaction in the session and streams back their responses.
+fn respond<S>(
+    session: Session,
+    mut stream: S,
+    call_deadline: Option<std::time::Instant>,
+) -> impl Stream<Item = Result<proto::Response, tonic::Status>>
+where
+    S: Stream<Item = Result<proto::Request, tonic::Status>> + Unpin,
+{
+    async_stream::stream! {
+        // Requests are passed to the session as soon as they arrive so that Cancel
+        // can be handled while a query is running. Their results are sent back in order.
+        let mut pending: VecDeque<ResultReceiver> = VecDeque::new();
+        let mut receiving = true;
+        loop {
+            let event = tokio::select! {
+                request = stream.next(), if receiving => Event::Request(request),
+                result = next_result(&mut pending), if !pending.is_empty() => Event::Result(result),
+                else => break,
+            };
+            match event {
+                Event::Request(Some(Ok(request))) => match request.message {
+                    Some(proto::request::Message::Cancel(_)) => session.cancel(),
+                    Some(message) => {
+                        let results = session
+                            .send(message, call_deadline)
+                            .unwrap_or_else(failed);
+                        pending.push_back(results);
                     }
-                    log::info!("DONE");
-                    yield Err(tonic::Status::ok("Completed successfully."))
-                };
-                Ok(tonic::Response::new(
-                    Box::pin(output) as Self::TransactionStream
-                ))
+                    None => pending.push_back(failed(crate::error::Error::ProtocolError {
+                        message: String::from("Unknown type of request received"),
+                    })),
+                },
+                Event::Request(Some(Err(status))) => {
+                    yield Err(status);
+                    break;
+                }
+                Event::Request(None) => receiving = false,
+                Event::Result(Some(result)) => yield Ok(response(result)),
+                Event::Result(None) => {
+                    pending.pop_front();
+                }
+            }
+        }
+        log::info!("DONE");
+        yield Err(tonic::Status::ok("Completed successfully."))
+    }
+}
+
+enum Event {
+    Request(Option<Result<proto::Request, tonic::Status>>),
+    Result(Option<crate::error::Result<proto::response::QueryResult>>),
+}
+
+async fn next_result(
+    pending: &mut VecDeque<ResultReceiver>,
+) -> Option<crate::error::Result<proto::response::QueryResult>> {
+    match pending.front_mut() {
+        Some(results) => results.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn failed(err: crate::error::Error) -> ResultReceiver {
+    let (sender, receiver) = tokio::sync::mpsc::channel(1);
+    let _ = sender.try_send(Err(err));
+    receiver
+}
+
+/// Parses grpc-timeout header, refer to https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md
+fn grpc_timeout(metadata: &tonic::metadata::MetadataMap) -> Option<std::time::Duration> {
+    let value = metadata.get("grpc-timeout")?.to_str().ok()?;
+    let (amount, unit) = value.split_at(value.len().checked_sub(1)?);
+    // TimeoutValue is a positive integer of at most 8 digits.
+    if amount.is_empty() || amount.len() > 8 || !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = amount.parse().ok()?;
+    match unit {
+        "H" => Some(std::time::Duration::from_secs(amount * 3600)),
+        "M" => Some(std::time::Duration::from_secs(amount * 60)),
+        "S" => Some(std::time::Duration::from_secs(amount)),
+        "m" => Some(std::time::Duration::from_millis(amount)),
+        "u" => Some(std::time::Duration::from_micros(amount)),
+        "n" => Some(std::time::Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+fn response(result: crate::error::Result<proto::response::QueryResult>) -> proto::Response {
+    match result {
+        Ok(result) => proto::Response {
+            result: Some(proto::response::Result::Success(result)),
+        },
+        Err(err) => {
+            log::warn!("Query failed: {}", err);
+            proto::Response {
+                result: Some(proto::response::Result::Error(err.into())),
             }
-            Some(Err(err)) => Err(tonic::Status::internal(err.to_string())),
-            None => Err(tonic::Status::ok("No query was sent.")),
         }
     }
 }
 
 impl DuckDbService {
     pub fn new() -> DuckDbService {
-        DuckDbService {}
+        DuckDbService {
+            databases: std::sync::Arc::new(Registry::default()),
+        }
     }
 
     pub fn new_server() -> grpc::DbServiceServer<DuckDbService> {
         grpc::DbServiceServer::new(DuckDbService::new())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeout(value: &str) -> Option<std::time::Duration> {
+        let mut metadata = tonic::metadata::MetadataMap::new();
+        metadata.insert("grpc-timeout", value.parse().unwrap());
+        grpc_timeout(&metadata)
+    }
+
+    #[test]
+    fn parse_grpc_timeout() {
+        assert_eq!(timeout("2H"), Some(std::time::Duration::from_secs(7200)));
+        assert_eq!(timeout("3M"), Some(std::time::Duration::from_secs(180)));
+        assert_eq!(timeout("10S"), Some(std::time::Duration::from_secs(10)));
+        assert_eq!(timeout("250m"), Some(std::time::Duration::from_millis(250)));
+        assert_eq!(timeout("7u"), Some(std::time::Duration::from_micros(7)));
+        assert_eq!(timeout("99n"), Some(std::time::Duration::from_nanos(99)));
+        assert_eq!(
+            timeout("99999999H"),
+            Some(std::time::Duration::from_secs(99999999 * 3600))
+        );
+    }
+
+    fn query(sql: &str) -> proto::Request {
+        proto::Request {
+            message: Some(proto::request::Message::Query(proto::Query {
+                kind: Some(proto::query::Kind::Rows(proto::query::QueryRows {
+                    query: String::from(sql),
+                    params: None,
+                })),
+                timeout: None,
+            })),
+        }
+    }
+
+    #[tokio::test]
+    async fn keep_stream_open_after_error() {
+        let connect = proto::Connect {
+            file_name: String::from(":memory:"),
+            ..Default::default()
+        };
+        let session = Session::connect(connect, std::sync::Arc::new(Registry::default()))
+            .await
+            .unwrap();
+        let requests =
+            tokio_stream::iter([Ok(query("SELECT * FROM missing")), Ok(query("SELECT 1"))]);
+        let responses: Vec<_> = respond(session, requests, None).collect().await;
+
+        assert_eq!(responses.len(), 3, "{:?}", responses);
+        assert!(
+            matches!(
+                &responses[0],
+                Ok(proto::Response {
+                    result: Some(proto::response::Result::Error(_))
+                })
+            ),
+            "{:?}",
+            responses[0]
+        );
+        assert!(
+            matches!(
+                &responses[1],
+                Ok(proto::Response {
+                    result: Some(proto::response::Result::Success(_))
+                })
+            ),
+            "{:?}",
+            responses[1]
+        );
+        let completed = responses[2].as_ref().unwrap_err();
+        assert_eq!(completed.code(), tonic::Code::Ok);
+    }
+
+    #[test]
+    fn ignore_invalid_grpc_timeout() {
+        assert_eq!(grpc_timeout(&tonic::metadata::MetadataMap::new()), None);
+        for value in [
+            "",
+            "S",
+            "10",
+            "10s",
+            "-1S",
+            "+1S",
+            "1.5S",
+            "123456789n",
+            "99999999999999999H",
+        ] {
+            assert_eq!(timeout(value), None, "{:?}", value);
+        }
+    }
+}
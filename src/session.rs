@@ -0,0 +1,423 @@
+use crate::database::Registry;
+use crate::error::{Error, Result};
+use crate::gduck::{Append, Canceller, Gduck, Prepared};
+use crate::proto;
+
+/// Number of results buffered per query before the session worker waits for the client.
+const RESULT_BUFFER_SIZE: usize = 16;
+
+type ResultSender = tokio::sync::mpsc::Sender<Result<proto::response::QueryResult>>;
+pub type ResultReceiver = tokio::sync::mpsc::Receiver<Result<proto::response::QueryResult>>;
+
+/// A DuckDB session running on a dedicated thread.
+///
+/// Requests are handed over to the thread through a channel so that long running
+/// queries do not block the async runtime. The session ends when this handle is dropped,
+/// which cancels the running request, and a transaction left open at that time is rolled back.
+pub struct Session {
+    requests: std::sync::mpsc::Sender<Request>,
+    canceller: Canceller,
+}
+
+/// Append in progress, which keeps the error of the first request that failed
+/// to report it in response to EndAppend.
+enum Appending<'g> {
+    Rows(Append<'g>),
+    Failed(Error),
+}
+
+/// State of a session kept across requests.
+#[derive(Default)]
+struct State<'g> {
+    appending: Option<Appending<'g>>,
+    // Statements prepared by the client by their handles
+    prepared: std::collections::HashMap<u64, Prepared<'g>>,
+    last_handle: u64,
+}
+
+type Request = (
+    proto::request::Message,
+    Option<std::time::Instant>,
+    ResultSender,
+);
+
+impl Session {
+    pub async fn connect(
+        mut connect: proto::Connect,
+        databases: std::sync::Arc<Registry>,
+    ) -> Result<Session> {
+        let (requests, receiver) = std::sync::mpsc::channel();
+        let (connected, on_connected) = tokio::sync::oneshot::channel();
+
+        // Sessions last as long as their clients stay connected, so each runs on a thread
+        // of its own instead of holding one of the limited threads of the blocking pool.
+        let runtime = tokio::runtime::Handle::current();
+        std::thread::Builder::new()
+            .name(String::from("gduck-session"))
+            .spawn(move || {
+                // Leases of in-memory databases schedule their expiry on the runtime.
+                let _runtime = runtime.enter();
+                let storages = std::mem::take(&mut connect.storages);
+                match databases
+                    .open(connect)
+                    .and_then(|database| Gduck::connect(database, storages))
+                {
+                    Ok(gduck) => {
+                        if connected.send(Ok(gduck.canceller())).is_ok() {
+                            Self::serve(gduck, receiver);
+                        }
+                    }
+                    Err(err) => {
+                        let _ = connected.send(Err(err));
+                    }
+                }
+            })
+            .map_err(|err| Error::internal(format!("cannot start session worker: {}", err)))?;
+
+        let canceller = on_connected
+            .await
+            .map_err(|_| Error::internal("session worker exited before connecting"))??;
+        Ok(Session {
+            requests,
+            canceller,
+        })
+    }
+
+    /// Sends the request to the session worker and returns a receiver of its results.
+    /// The request fails with `Error::DeadlineExceeded` if it is still running at
+    /// `call_deadline` or when its own timeout has passed since it started running.
+    pub fn send(
+        &self,
+        message: proto::request::Message,
+        call_deadline: Option<std::time::Instant>,
+    ) -> Result<ResultReceiver> {
+        let (sender, receiver) = tokio::sync::mpsc::channel(RESULT_BUFFER_SIZE);
+        self.requests
+            .send((message, call_deadline, sender))
+            .map_err(|_| Error::internal("session worker has already exited"))?;
+        Ok(receiver)
+    }
+
+    /// Cancels the request the session worker is processing.
+    pub fn cancel(&self) {
+        self.canceller.cancel();
+    }
+
+    fn serve(gduck: Gduck, requests: std::sync::mpsc::Receiver<Request>) {
+        let mut state = State::default();
+        while let Ok((message, call_deadline, results)) = requests.recv() {
+            // Requests queued by a client that has gone away are not run.
+            if results.is_closed() {
+                continue;
+            }
+            let outcome = deadline(&message, call_deadline).and_then(|deadline| {
+                gduck.start_request(deadline);
+                let _timer =
+                    deadline.map(|deadline| DeadlineTimer::start(deadline, gduck.canceller()));
+                Self::handle(&gduck, &mut state, message, |result| {
+                    results
+                        .blocking_send(Ok(result))
+                        .map_err(|_| Error::internal("client has gone away"))
+                })
+                .map_err(|err| gduck.interruption(err))
+            });
+            if let Err(err) = outcome {
+                let _ = results.blocking_send(Err(err));
+            }
+        }
+        // Rows being appended are flushed before the transaction is rolled back.
+        drop(state);
+        if let Err(err) = gduck.rollback_if_open() {
+            log::warn!("Failed to roll back the open transaction: {}", err);
+        }
+        log::debug!("Session worker finished");
+    }
+
+    fn handle<'g, F: FnMut(proto::response::QueryResult) -> Result<()>>(
+        gduck: &'g Gduck,
+        state: &mut State<'g>,
+        message: proto::request::Message,
+        mut sink: F,
+    ) -> Result<()> {
+        let appending = &mut state.appending;
+        match message {
+            proto::request::Message::AppendRows(rows) if appending.is_some() => {
+                let failed = match appending {
+                    Some(Appending::Rows(append)) => append.append_rows(rows).err(),
+                    _ => None,
+                };
+                if let Some(err) = failed {
+                    *appending = Some(Appending::Failed(err));
+                }
+                Ok(())
+            }
+            proto::request::Message::EndAppend(_) if appending.is_some() => {
+                match appending.take() {
+                    Some(Appending::Rows(append)) => sink(append.finish()?),
+                    Some(Appending::Failed(err)) => Err(err),
+                    None => Ok(()),
+                }
+            }
+            proto::request::Message::AppendRows(_) | proto::request::Message::EndAppend(_) => {
+                Err(Error::ProtocolError {
+                    message: String::from("Append message is required before rows to append"),
+                })
+            }
+            _ if appending.is_some() => Err(Error::ProtocolError {
+                message: String::from("Only rows to append are allowed until EndAppend"),
+            }),
+            proto::request::Message::Append(append) => {
+                *appending = Some(match gduck.append(append) {
+                    Ok(append) => Appending::Rows(append),
+                    Err(err) => Appending::Failed(err),
+                });
+                Ok(())
+            }
+            proto::request::Message::Query(query) => {
+                Self::run(gduck, &mut state.prepared, query, sink)
+            }
+            proto::request::Message::Begin(_) => sink(gduck.begin()?),
+            proto::request::Message::Commit(_) => sink(gduck.commit()?),
+            proto::request::Message::Rollback(_) => sink(gduck.rollback()?),
+            proto::request::Message::Connect(_) => Err(Error::ProtocolError {
+                message: String::from("Connect message is allowed only at the beginning"),
+            }),
+            proto::request::Message::Cancel(_) => Ok(()),
+            proto::request::Message::Prepare(prepare) => {
+                let prepared = gduck.prepare(prepare.query)?;
+                state.last_handle += 1;
+                let result = prepared.prepared(state.last_handle);
+                state.prepared.insert(state.last_handle, prepared);
+                sink(result)
+            }
+            proto::request::Message::ClosePrepared(close) => {
+                if state.prepared.remove(&close.handle).is_none() {
+                    return Err(unknown_prepared(close.handle));
+                }
+                sink(proto::response::QueryResult {
+                    kind: Some(proto::response::query_result::Kind::Ok(())),
+                    ..Default::default()
+                })
+            }
+        }
+    }
+
+    fn run<'g, F: FnMut(proto::response::QueryResult) -> Result<()>>(
+        gduck: &'g Gduck,
+        prepared: &mut std::collections::HashMap<u64, Prepared<'g>>,
+        query: proto::Query,
+        mut sink: F,
+    ) -> Result<()> {
+        match query.kind {
+            Some(proto::query::Kind::Execute(q)) => {
+                sink(gduck.execute(q.query, q.params.unwrap_or_default())?)
+            }
+            Some(proto::query::Kind::Value(q)) => {
+                sink(gduck.query_value(q.query, q.params.unwrap_or_default())?)
+            }
+            Some(proto::query::Kind::Rows(q)) => {
+                sink(gduck.query_rows(q.query, q.params.unwrap_or_default())?)
+            }
+            Some(proto::query::Kind::StreamRows(q)) => gduck.stream_rows(
+                q.query,
+                q.params.unwrap_or_default(),
+                q.batch_size as usize,
+                sink,
+            ),
+            Some(proto::query::Kind::Arrow(q)) => {
+                gduck.query_arrow(q.query, q.params.unwrap_or_default(), sink)
+            }
+            Some(proto::query::Kind::Ctas(ctas)) => sink(gduck.create_table_as(
+                ctas.table_name,
+                ctas.query,
+                ctas.params.unwrap_or_default(),
+            )?),
+            Some(proto::query::Kind::Export(q)) => match q.location {
+                Some(l) => sink(gduck.export(
+                    q.query,
+                    q.params.unwrap_or_default(),
+                    crate::uri::Uri::try_from(l)?,
+                    q.format,
+                )?),
+                None => Err(Error::InvalidRequest(String::from(
+                    "Location to export to is required.",
+                ))),
+            },
+            Some(proto::query::Kind::ExecuteBatch(q)) => {
+                sink(gduck.execute_batch(q.query, q.params)?)
+            }
+            Some(proto::query::Kind::LoadArrow(load)) => sink(gduck.load_arrow(load)?),
+            Some(proto::query::Kind::ExecutePrepared(q)) => match prepared.get_mut(&q.handle) {
+                Some(statement) => statement.run(q, sink),
+                None => Err(unknown_prepared(q.handle)),
+            },
+            kind => Err(Error::ProtocolError {
+                message: format!("Unknown query: {:?}", kind),
+            }),
+        }
+    }
+}
+
+/// Interrupts the running statement when the deadline of the request comes,
+/// unless the request finishes and drops this before that.
+struct DeadlineTimer(tokio::task::JoinHandle<()>);
+
+impl DeadlineTimer {
+    fn start(deadline: std::time::Instant, canceller: Canceller) -> DeadlineTimer {
+        DeadlineTimer(tokio::spawn(async move {
+            tokio::time::sleep_until(deadline.into()).await;
+            canceller.interrupt();
+        }))
+    }
+}
+
+impl Drop for Session {
+    // A client disconnecting or failing in the middle of a query stops it as Cancel does.
+    fn drop(&mut self) {
+        self.canceller.cancel();
+    }
+}
+
+impl Drop for DeadlineTimer {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Deadline of the request, which is the earlier of the deadline of the call and its own
+/// timeout counted from now, when it starts running.
+fn deadline(
+    message: &proto::request::Message,
+    call_deadline: Option<std::time::Instant>,
+) -> Result<Option<std::time::Instant>> {
+    let timeout = match message {
+        proto::request::Message::Query(proto::Query {
+            timeout: Some(timeout),
+            ..
+        }) => Some(
+            std::time::Duration::try_from(*timeout)
+                .map_err(|err| Error::InvalidRequest(format!("invalid timeout: {}", err)))?,
+        ),
+        _ => None,
+    };
+    // A timeout too long to be represented never passes.
+    let deadline = timeout.and_then(|timeout| std::time::Instant::now().checked_add(timeout));
+    Ok(match (deadline, call_deadline) {
+        (Some(deadline), Some(call_deadline)) => Some(deadline.min(call_deadline)),
+        (deadline, call_deadline) => deadline.or(call_deadline),
+    })
+}
+
+fn unknown_prepared(handle: u64) -> Error {
+    Error::InvalidRequest(format!("unknown prepared statement {}", handle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Scans long enough to be still running when it is stopped
+    const LONG_QUERY: &str = "SELECT count(*) FROM range(1000000000000) WHERE random() < 0";
+
+    async fn session() -> Session {
+        let connect = proto::Connect {
+            file_name: String::from(":memory:"),
+            ..Default::default()
+        };
+        Session::connect(connect, std::sync::Arc::new(Registry::default()))
+            .await
+            .unwrap()
+    }
+
+    fn query(sql: &str, timeout: Option<std::time::Duration>) -> proto::request::Message {
+        proto::request::Message::Query(proto::Query {
+            kind: Some(proto::query::Kind::Rows(proto::query::QueryRows {
+                query: String::from(sql),
+                params: None,
+            })),
+            timeout: timeout.map(|timeout| timeout.try_into().unwrap()),
+        })
+    }
+
+    async fn result(mut results: ResultReceiver) -> Result<proto::response::QueryResult> {
+        results.recv().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn cancel_running_query() {
+        let session = session().await;
+        let started = std::time::Instant::now();
+        let results = session.send(query(LONG_QUERY, None), None).unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        session.cancel();
+        let queried = result(results).await;
+        assert!(matches!(queried, Err(Error::Cancelled)), "{:?}", queried);
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+
+        let results = session.send(query("SELECT 1", None), None).unwrap();
+        assert!(result(results).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn cancel_running_query_on_drop() {
+        let session = session().await;
+        let results = session.send(query(LONG_QUERY, None), None).unwrap();
+        let queued = session.send(query(LONG_QUERY, None), None).unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        drop((session, queued));
+        let queried = tokio::time::timeout(std::time::Duration::from_secs(10), result(results))
+            .await
+            .unwrap();
+        assert!(matches!(queried, Err(Error::Cancelled)), "{:?}", queried);
+    }
+
+    #[tokio::test]
+    async fn interrupt_query_at_deadline() {
+        let session = session().await;
+        let timeout = std::time::Duration::from_millis(200);
+        let results = session
+            .send(query(LONG_QUERY, Some(timeout)), None)
+            .unwrap();
+        let queried = result(results).await;
+        assert!(
+            matches!(queried, Err(Error::DeadlineExceeded)),
+            "{:?}",
+            queried
+        );
+
+        let deadline = std::time::Instant::now() + timeout;
+        let results = session
+            .send(query(LONG_QUERY, None), Some(deadline))
+            .unwrap();
+        let queried = result(results).await;
+        assert!(
+            matches!(queried, Err(Error::DeadlineExceeded)),
+            "{:?}",
+            queried
+        );
+    }
+
+    #[tokio::test]
+    async fn start_timeout_when_request_runs() {
+        let session = session().await;
+        let running = session.send(query(LONG_QUERY, None), None).unwrap();
+        let timeout = std::time::Duration::from_millis(100);
+        let waiting = session
+            .send(query("SELECT 1", Some(timeout)), None)
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        session.cancel();
+        assert!(matches!(result(running).await, Err(Error::Cancelled)));
+        assert!(result(waiting).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn never_time_out_beyond_representable_instants() {
+        let session = session().await;
+        let timeout = std::time::Duration::from_secs(i64::MAX as u64);
+        let results = session
+            .send(query("SELECT 1", Some(timeout)), None)
+            .unwrap();
+        assert!(result(results).await.is_ok());
+    }
+}
@@ -1,19 +1,381 @@
+use crate::error::{Error, Result};
+
 #[derive(Clone, Debug)]
 #[allow(dead_code)]
 pub enum Uri {
     LocalFileSystem(std::path::PathBuf),
-    S3 { bucket: String, key: String },
-    Gcs { bucket: String, key: String },
+    S3 {
+        bucket: String,
+        key: String,
+        options: S3Options,
+    },
+    Gcs {
+        bucket: String,
+        key: String,
+        credential: String,
+    },
+    Azure {
+        account_name: String,
+        container: String,
+        path: String,
+        // abfss:// instead of az://
+        data_lake: bool,
+        credential: String,
+    },
     Raw(String),
 }
 
+/// How DuckDB connects to an S3 compatible storage.
+#[derive(Clone, Debug, Default)]
+pub struct S3Options {
+    pub region: String,
+    // Host and port, AWS S3 if empty
+    pub endpoint: String,
+    pub path_style: bool,
+    pub use_ssl: bool,
+    // Name of the credentials given to the server, anonymous if empty
+    pub credential: String,
+}
+
+/// Credentials which the server is given by environment variables prefixed with
+/// `GDUCK_CREDENTIAL_<NAME>_`, e.g. `GDUCK_CREDENTIAL_MINIO_KEY_ID`.
+struct Credential<'a>(&'a str);
+
+impl Credential<'_> {
+    fn var(&self, key: &str) -> Option<String> {
+        let name = self.0.to_uppercase().replace(['-', '.'], "_");
+        std::env::var(format!("GDUCK_CREDENTIAL_{}_{}", name, key)).ok()
+    }
+
+    fn required(&self, key: &str) -> Result<String> {
+        self.var(key).ok_or_else(|| {
+            Error::InvalidRequest(format!("unknown credential {:?} or its {}", self.0, key))
+        })
+    }
+
+    /// Fails unless the URI is under one of the comma separated URI prefixes of `SCOPE`,
+    /// e.g. `s3://bucket/prefix/,gs://bucket/`, which limits the objects clients can access
+    /// with the credential.
+    fn check_scope(&self, uri: &Uri) -> Result<()> {
+        let uri = uri.to_string();
+        let scope = self.required("SCOPE")?;
+        let mut prefixes = scope
+            .split(',')
+            .map(str::trim)
+            .filter(|prefix| !prefix.is_empty());
+        if prefixes.any(|prefix| within(&uri, prefix)) {
+            Ok(())
+        } else {
+            Err(Error::InvalidRequest(format!(
+                "credential {:?} is not allowed for {}",
+                self.0, uri
+            )))
+        }
+    }
+
+    /// Fails unless the endpoint is `ENDPOINT`, or AWS S3 if it is not given, so that the
+    /// credential is not sent to other hosts.
+    fn check_endpoint(&self, endpoint: &str) -> Result<()> {
+        let allowed = self.var("ENDPOINT").unwrap_or_default();
+        if endpoint.eq_ignore_ascii_case(allowed.trim()) {
+            Ok(())
+        } else {
+            Err(Error::InvalidRequest(format!(
+                "credential {:?} is not allowed for endpoint {:?}",
+                self.0, endpoint
+            )))
+        }
+    }
+
+    // Key pair of S3 and HMAC key of GCS
+    fn key_params(&self, params: &mut Vec<String>) -> Result<()> {
+        params.push(format!("KEY_ID {}", literal(&self.required("KEY_ID")?)));
+        params.push(format!("SECRET {}", literal(&self.required("SECRET")?)));
+        if let Some(token) = self.var("SESSION_TOKEN") {
+            params.push(format!("SESSION_TOKEN {}", literal(&token)));
+        }
+        Ok(())
+    }
+}
+
+// Whether the URI is the prefix or under it, where a prefix not ending with a slash covers only
+// whole path segments, e.g. "s3://bucket" covers "s3://bucket/a" but not "s3://bucket-a".
+fn within(uri: &str, prefix: &str) -> bool {
+    match uri.strip_prefix(prefix) {
+        Some(rest) => prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// SQL string literal of the value.
+pub(crate) fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+impl Uri {
+    /// Statement creating a DuckDB secret named `name` to access the URI and the objects
+    /// under it, or None if it needs no secret.
+    pub fn create_secret(&self, name: &str) -> Result<Option<String>> {
+        let mut params = Vec::new();
+        match self {
+            Uri::S3 { options, .. } => {
+                params.push(String::from("TYPE S3"));
+                params.push(format!("USE_SSL {}", options.use_ssl));
+                if !options.region.is_empty() {
+                    params.push(format!("REGION {}", literal(&options.region)));
+                }
+                if !options.endpoint.is_empty() {
+                    params.push(format!("ENDPOINT {}", literal(&options.endpoint)));
+                }
+                if options.path_style {
+                    params.push(String::from("URL_STYLE 'path'"));
+                }
+                if !options.credential.is_empty() {
+                    let credential = Credential(&options.credential);
+                    credential.check_scope(self)?;
+                    credential.check_endpoint(&options.endpoint)?;
+                    credential.key_params(&mut params)?;
+                }
+            }
+            Uri::Gcs { credential, .. } => {
+                params.push(String::from("TYPE GCS"));
+                if !credential.is_empty() {
+                    let credential = Credential(credential);
+                    credential.check_scope(self)?;
+                    credential.key_params(&mut params)?;
+                }
+            }
+            Uri::Azure {
+                account_name,
+                credential,
+                ..
+            } => {
+                params.push(String::from("TYPE AZURE"));
+                if credential.is_empty() {
+                    params.push(String::from("PROVIDER CONFIG"));
+                    params.push(format!("ACCOUNT_NAME {}", literal(account_name)));
+                } else {
+                    let credential = Credential(credential);
+                    credential.check_scope(self)?;
+                    let connection_string = credential.required("CONNECTION_STRING")?;
+                    params.push(format!("CONNECTION_STRING {}", literal(&connection_string)));
+                }
+            }
+            Uri::LocalFileSystem(_) | Uri::Raw(_) => return Ok(None),
+        }
+        params.push(format!("SCOPE {}", literal(&self.to_string())));
+        Ok(Some(format!(
+            "CREATE OR REPLACE TEMPORARY SECRET {} ({})",
+            name,
+            params.join(", ")
+        )))
+    }
+}
+
 impl std::fmt::Display for Uri {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Uri::LocalFileSystem(path) => write!(f, "{}", path.to_string_lossy()),
-            Uri::S3 { bucket, key } => write!(f, "s3://{}/{}", bucket, key),
-            Uri::Gcs { bucket, key } => write!(f, "gs://{}/{}", bucket, key),
+            Uri::S3 { bucket, key, .. } => write!(f, "s3://{}/{}", bucket, key),
+            Uri::Gcs { bucket, key, .. } => write!(f, "gs://{}/{}", bucket, key),
+            Uri::Azure {
+                container,
+                path,
+                data_lake: false,
+                ..
+            } => write!(f, "az://{}/{}", container, path),
+            Uri::Azure {
+                container,
+                path,
+                data_lake: true,
+                ..
+            } => write!(f, "abfss://{}/{}", container, path),
             Uri::Raw(raw) => write!(f, "{}", raw),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s3(credential: &str) -> Uri {
+        Uri::S3 {
+            bucket: String::from("bucket"),
+            key: String::from("prefix/"),
+            options: S3Options {
+                region: String::from("us-east-1"),
+                endpoint: String::from("localhost:9000"),
+                path_style: true,
+                use_ssl: false,
+                credential: credential.to_owned(),
+            },
+        }
+    }
+
+    #[test]
+    fn literal_escapes_quotes() {
+        assert_eq!(literal("it's"), "'it''s'");
+        assert_eq!(literal(""), "''");
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(s3("").to_string(), "s3://bucket/prefix/");
+        let gcs = Uri::Gcs {
+            bucket: String::from("bucket"),
+            key: String::from("a.parquet"),
+            credential: String::new(),
+        };
+        assert_eq!(gcs.to_string(), "gs://bucket/a.parquet");
+        let azure = |data_lake| Uri::Azure {
+            account_name: String::from("account"),
+            container: String::from("container"),
+            path: String::from("dir/a.csv"),
+            data_lake,
+            credential: String::new(),
+        };
+        assert_eq!(azure(false).to_string(), "az://container/dir/a.csv");
+        assert_eq!(azure(true).to_string(), "abfss://container/dir/a.csv");
+        let local = Uri::LocalFileSystem(std::path::PathBuf::from("/tmp/a.json"));
+        assert_eq!(local.to_string(), "/tmp/a.json");
+    }
+
+    #[test]
+    fn create_secret_without_credential() {
+        assert_eq!(
+            s3("").create_secret("s").unwrap().unwrap(),
+            "CREATE OR REPLACE TEMPORARY SECRET s (TYPE S3, USE_SSL false, \
+             REGION 'us-east-1', ENDPOINT 'localhost:9000', URL_STYLE 'path', \
+             SCOPE 's3://bucket/prefix/')"
+        );
+        let azure = Uri::Azure {
+            account_name: String::from("account"),
+            container: String::from("container"),
+            path: String::new(),
+            data_lake: false,
+            credential: String::new(),
+        };
+        assert_eq!(
+            azure.create_secret("s").unwrap().unwrap(),
+            "CREATE OR REPLACE TEMPORARY SECRET s (TYPE AZURE, PROVIDER CONFIG, \
+             ACCOUNT_NAME 'account', SCOPE 'az://container/')"
+        );
+        let local = Uri::LocalFileSystem(std::path::PathBuf::from("/tmp"));
+        assert!(local.create_secret("s").unwrap().is_none());
+    }
+
+    // Each test reads credentials of its own names, since tests run in parallel.
+    #[test]
+    fn create_secret_with_credential() {
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_MINIO_KEY_ID", "id");
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_MINIO_SECRET", "it's secret");
+        std::env::set_var(
+            "GDUCK_CREDENTIAL_TEST_MINIO_SCOPE",
+            "s3://other/, s3://bucket/",
+        );
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_MINIO_ENDPOINT", "localhost:9000");
+        assert_eq!(
+            s3("test-minio").create_secret("s").unwrap().unwrap(),
+            "CREATE OR REPLACE TEMPORARY SECRET s (TYPE S3, USE_SSL false, \
+             REGION 'us-east-1', ENDPOINT 'localhost:9000', URL_STYLE 'path', \
+             KEY_ID 'id', SECRET 'it''s secret', SCOPE 's3://bucket/prefix/')"
+        );
+
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_GCS_KEY_ID", "id");
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_GCS_SECRET", "secret");
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_GCS_SESSION_TOKEN", "token");
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_GCS_SCOPE", "gs://bucket");
+        let gcs = Uri::Gcs {
+            bucket: String::from("bucket"),
+            key: String::new(),
+            credential: String::from("test.gcs"),
+        };
+        assert_eq!(
+            gcs.create_secret("s").unwrap().unwrap(),
+            "CREATE OR REPLACE TEMPORARY SECRET s (TYPE GCS, KEY_ID 'id', SECRET 'secret', \
+             SESSION_TOKEN 'token', SCOPE 'gs://bucket/')"
+        );
+
+        std::env::set_var(
+            "GDUCK_CREDENTIAL_TEST_AZURE_CONNECTION_STRING",
+            "AccountName=account",
+        );
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_AZURE_SCOPE", "abfss://container/");
+        let azure = Uri::Azure {
+            account_name: String::from("account"),
+            container: String::from("container"),
+            path: String::new(),
+            data_lake: true,
+            credential: String::from("Test_Azure"),
+        };
+        assert_eq!(
+            azure.create_secret("s").unwrap().unwrap(),
+            "CREATE OR REPLACE TEMPORARY SECRET s (TYPE AZURE, \
+             CONNECTION_STRING 'AccountName=account', SCOPE 'abfss://container/')"
+        );
+    }
+
+    #[test]
+    fn reject_credential_outside_its_scope() {
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_SCOPED_KEY_ID", "id");
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_SCOPED_SECRET", "secret");
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_SCOPED_ENDPOINT", "localhost:9000");
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_SCOPED_SCOPE", "s3://bucket/prefix");
+        let s3 = |bucket: &str, key: &str, endpoint: &str| Uri::S3 {
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            options: S3Options {
+                endpoint: endpoint.to_owned(),
+                credential: String::from("test-scoped"),
+                ..Default::default()
+            },
+        };
+
+        for (bucket, key) in [("bucket", "prefix"), ("bucket", "prefix/a.parquet")] {
+            let uri = s3(bucket, key, "localhost:9000");
+            assert!(uri.create_secret("s").is_ok(), "{}", uri);
+        }
+        for uri in [
+            s3("bucket", "", "localhost:9000"),
+            s3("bucket", "prefix-a/a.parquet", "localhost:9000"),
+            s3("other", "prefix/", "localhost:9000"),
+            s3("bucket", "prefix/", "attacker:9000"),
+            s3("bucket", "prefix/", ""),
+        ] {
+            assert!(
+                matches!(uri.create_secret("s"), Err(Error::InvalidRequest(_))),
+                "{:?}",
+                uri
+            );
+        }
+
+        // Credentials without scope are not used at all.
+        std::env::set_var(
+            "GDUCK_CREDENTIAL_TEST_UNSCOPED_CONNECTION_STRING",
+            "AccountName=a",
+        );
+        let azure = Uri::Azure {
+            account_name: String::from("a"),
+            container: String::from("container"),
+            path: String::new(),
+            data_lake: false,
+            credential: String::from("test-unscoped"),
+        };
+        assert!(matches!(
+            azure.create_secret("s"),
+            Err(Error::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn create_secret_with_unknown_credential() {
+        std::env::set_var("GDUCK_CREDENTIAL_TEST_PARTIAL_KEY_ID", "id");
+        for credential in ["test-unknown", "test-partial"] {
+            assert!(matches!(
+                s3(credential).create_secret("s"),
+                Err(Error::InvalidRequest(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,722 @@
+//! Conversion of query results, which DuckDB returns as Arrow record batches, into proto values.
+//!
+//! Values are read from the Arrow arrays directly instead of through `duckdb::Row`
+//! because duckdb-rs panics on Arrow types it does not know, e.g. UHUGEINT.
+//! Databases are opened with `arrow_lossless_conversion` so that DuckDB specific types,
+//! e.g. HUGEINT and BIT, are passed as extension types instead of the closest Arrow types.
+
+use duckdb::arrow::array::{Array, ArrayRef, AsArray, BooleanArray, Decimal128Array, StringArray};
+use duckdb::arrow::datatypes::{self, DataType, Field, IntervalUnit, Schema, SchemaRef, TimeUnit};
+use duckdb::arrow::record_batch::RecordBatch;
+
+use crate::error::{Error, Result};
+use crate::proto;
+use crate::proto::scalar_value::Kind;
+
+// DuckDB's default Arrow type of HUGEINT
+const HUGEINT: DataType = DataType::Decimal128(38, 0);
+
+/// Name of the extension type of the field: the DuckDB type name for types DuckDB passes as
+/// arrow.opaque, e.g. "uhugeint", or the name of a canonical extension type, e.g. "arrow.uuid".
+pub fn extension_type(field: &Field) -> Option<&str> {
+    const TYPE_NAME: &str = "\"type_name\":\"";
+
+    let name = field.metadata().get("ARROW:extension:name")?;
+    if name != "arrow.opaque" {
+        return Some(name);
+    }
+    let metadata = field.metadata().get("ARROW:extension:metadata")?;
+    let (_, rest) = metadata.split_once(TYPE_NAME)?;
+    rest.split_once('"').map(|(name, _)| name)
+}
+
+fn uuid_text(bytes: &[u8]) -> String {
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+// VARINT, which DuckDB calls BIGNUM since 1.4, is a header of 3 bytes telling the sign followed by the big-endian bytes of the
+// absolute value, all of which are inverted for negative values.
+fn varint_text(bytes: &[u8]) -> Result<String> {
+    const BASE: u64 = 1_000_000_000;
+
+    if bytes.len() < 4 {
+        return Err(Error::internal("invalid VARINT value"));
+    }
+    let negative = bytes[0] & 0x80 == 0;
+    // Digits in base 10^9, the least significant first
+    let mut digits: Vec<u64> = Vec::new();
+    for byte in &bytes[3..] {
+        let mut carry = u64::from(if negative { !byte } else { *byte });
+        for digit in digits.iter_mut() {
+            let value = (*digit << 8) | carry;
+            (*digit, carry) = (value % BASE, value / BASE);
+        }
+        while carry > 0 {
+            digits.push(carry % BASE);
+            carry /= BASE;
+        }
+    }
+
+    let mut text = String::from(if negative { "-" } else { "" });
+    let mut digits = digits.iter().rev();
+    text.push_str(&digits.next().copied().unwrap_or(0).to_string());
+    for digit in digits {
+        text.push_str(&format!("{:09}", digit));
+    }
+    Ok(text)
+}
+
+pub fn rows(batch: &RecordBatch) -> Result<Vec<proto::Row>> {
+    let schema = batch.schema();
+    (0..batch.num_rows())
+        .map(|row| {
+            let values = schema
+                .fields()
+                .iter()
+                .zip(batch.columns())
+                .map(|(field, column)| {
+                    value(field, column.as_ref(), row)
+                        .map(|kind| proto::ScalarValue { kind: Some(kind) })
+                })
+                .collect::<Result<Vec<proto::ScalarValue>>>()?;
+            Ok(proto::Row { values })
+        })
+        .collect()
+}
+
+pub fn value(field: &Field, column: &dyn Array, row: usize) -> Result<Kind> {
+    if column.is_null(row) {
+        return Ok(Kind::NullValue(prost_types::NullValue::NullValue as i32));
+    }
+    match column.data_type() {
+        DataType::Null => Ok(Kind::NullValue(prost_types::NullValue::NullValue as i32)),
+        DataType::Boolean => Ok(Kind::BoolValue(column.as_boolean().value(row))),
+        DataType::Int8 if extension_type(field) == Some("arrow.bool8") => Ok(Kind::BoolValue(
+            column.as_primitive::<datatypes::Int8Type>().value(row) != 0,
+        )),
+        DataType::Int8 => Ok(Kind::IntValue(i64::from(
+            column.as_primitive::<datatypes::Int8Type>().value(row),
+        ))),
+        DataType::Int16 => Ok(Kind::IntValue(i64::from(
+            column.as_primitive::<datatypes::Int16Type>().value(row),
+        ))),
+        DataType::Int32 => Ok(Kind::IntValue(i64::from(
+            column.as_primitive::<datatypes::Int32Type>().value(row),
+        ))),
+        DataType::Int64 => Ok(Kind::IntValue(
+            column.as_primitive::<datatypes::Int64Type>().value(row),
+        )),
+        DataType::UInt8 => Ok(Kind::UintValue(u64::from(
+            column.as_primitive::<datatypes::UInt8Type>().value(row),
+        ))),
+        DataType::UInt16 => Ok(Kind::UintValue(u64::from(
+            column.as_primitive::<datatypes::UInt16Type>().value(row),
+        ))),
+        DataType::UInt32 => Ok(Kind::UintValue(u64::from(
+            column.as_primitive::<datatypes::UInt32Type>().value(row),
+        ))),
+        DataType::UInt64 => Ok(Kind::UintValue(
+            column.as_primitive::<datatypes::UInt64Type>().value(row),
+        )),
+        DataType::Float16 => Ok(Kind::DoubleValue(f64::from(
+            column.as_primitive::<datatypes::Float16Type>().value(row),
+        ))),
+        DataType::Float32 => Ok(Kind::DoubleValue(f64::from(
+            column.as_primitive::<datatypes::Float32Type>().value(row),
+        ))),
+        DataType::Float64 => Ok(Kind::DoubleValue(
+            column.as_primitive::<datatypes::Float64Type>().value(row),
+        )),
+        DataType::Decimal128(precision, scale) => Ok(Kind::DecimalValue(proto::Decimal {
+            value: column
+                .as_primitive::<datatypes::Decimal128Type>()
+                .value_as_string(row),
+            precision: u32::from(*precision),
+            scale: u32::try_from(*scale).unwrap_or_default(),
+        })),
+        DataType::Decimal256(precision, scale) => Ok(Kind::DecimalValue(proto::Decimal {
+            value: column
+                .as_primitive::<datatypes::Decimal256Type>()
+                .value_as_string(row),
+            precision: u32::from(*precision),
+            scale: u32::try_from(*scale).unwrap_or_default(),
+        })),
+        DataType::Utf8 => Ok(Kind::StrValue(column.as_string::<i32>().value(row).into())),
+        DataType::LargeUtf8 => Ok(Kind::StrValue(column.as_string::<i64>().value(row).into())),
+        DataType::Utf8View => Ok(Kind::StrValue(column.as_string_view().value(row).into())),
+        // TIMESTAMP WITH TIME ZONE is passed in UTC with the time zone of the session
+        DataType::Timestamp(unit, time_zone) => {
+            let (value, units_per_second) = match unit {
+                TimeUnit::Second => (
+                    column
+                        .as_primitive::<datatypes::TimestampSecondType>()
+                        .value(row),
+                    1,
+                ),
+                TimeUnit::Millisecond => (
+                    column
+                        .as_primitive::<datatypes::TimestampMillisecondType>()
+                        .value(row),
+                    1_000,
+                ),
+                TimeUnit::Microsecond => (
+                    column
+                        .as_primitive::<datatypes::TimestampMicrosecondType>()
+                        .value(row),
+                    1_000_000,
+                ),
+                TimeUnit::Nanosecond => (
+                    column
+                        .as_primitive::<datatypes::TimestampNanosecondType>()
+                        .value(row),
+                    1_000_000_000,
+                ),
+            };
+            let timestamp = prost_types::Timestamp {
+                seconds: value.div_euclid(units_per_second),
+                nanos: (value.rem_euclid(units_per_second) * (1_000_000_000 / units_per_second))
+                    as i32,
+            };
+            match time_zone {
+                Some(_) => Ok(Kind::TimestamptzValue(timestamp)),
+                None => Ok(Kind::DatetimeValue(timestamp)),
+            }
+        }
+        DataType::Date32 => column
+            .as_primitive::<datatypes::Date32Type>()
+            .value_as_date(row)
+            .map(|date| Kind::DateValue(proto::Date::from(date)))
+            .ok_or_else(|| Error::internal(format!("invalid date at row {}", row))),
+        DataType::Time64(TimeUnit::Microsecond) => column
+            .as_primitive::<datatypes::Time64MicrosecondType>()
+            .value_as_time(row)
+            .map(|time| Kind::TimeValue(proto::Time::from(time)))
+            .ok_or_else(|| Error::internal(format!("invalid time at row {}", row))),
+        DataType::Time64(TimeUnit::Nanosecond) => column
+            .as_primitive::<datatypes::Time64NanosecondType>()
+            .value_as_time(row)
+            .map(|time| Kind::TimeValue(proto::Time::from(time)))
+            .ok_or_else(|| Error::internal(format!("invalid time at row {}", row))),
+        DataType::Interval(IntervalUnit::MonthDayNano) => {
+            let interval = column
+                .as_primitive::<datatypes::IntervalMonthDayNanoType>()
+                .value(row);
+            Ok(Kind::IntervalValue(proto::Interval {
+                months: interval.months,
+                days: interval.days,
+                nanos: interval.nanoseconds,
+            }))
+        }
+        DataType::Interval(IntervalUnit::DayTime) => {
+            let interval = column
+                .as_primitive::<datatypes::IntervalDayTimeType>()
+                .value(row);
+            Ok(Kind::IntervalValue(proto::Interval {
+                months: 0,
+                days: interval.days,
+                nanos: i64::from(interval.milliseconds) * 1_000_000,
+            }))
+        }
+        DataType::Interval(IntervalUnit::YearMonth) => Ok(Kind::IntervalValue(proto::Interval {
+            months: column
+                .as_primitive::<datatypes::IntervalYearMonthType>()
+                .value(row),
+            days: 0,
+            nanos: 0,
+        })),
+        DataType::FixedSizeBinary(16) if extension_type(field) == Some("arrow.uuid") => Ok(
+            Kind::UuidValue(uuid_text(column.as_fixed_size_binary().value(row))),
+        ),
+        // TIMETZ is 40 bits of microseconds of the time followed by 24 bits of the offset,
+        // which is stored subtracted from the maximum offset so that values sort by instant.
+        DataType::FixedSizeBinary(8) if extension_type(field) == Some("time_tz") => {
+            let bytes = column.as_fixed_size_binary().value(row);
+            let bits = u64::from_le_bytes(
+                bytes
+                    .try_into()
+                    .map_err(|_| Error::internal("invalid TIMETZ value"))?,
+            );
+            let micros = bits >> 24;
+            let offset_seconds = proto::TimeTz::MAX_OFFSET_SECONDS - (bits & 0xFF_FFFF) as i32;
+            chrono::NaiveTime::from_num_seconds_from_midnight_opt(
+                (micros / 1_000_000) as u32,
+                (micros % 1_000_000 * 1_000) as u32,
+            )
+            .map(|time| {
+                Kind::TimetzValue(proto::TimeTz {
+                    time: Some(proto::Time::from(time)),
+                    offset_seconds,
+                })
+            })
+            .ok_or_else(|| Error::internal(format!("invalid time at row {}", row)))
+        }
+        DataType::FixedSizeBinary(16) if extension_type(field) == Some("hugeint") => {
+            let bytes = column.as_fixed_size_binary().value(row);
+            let value = i128::from_le_bytes(
+                bytes
+                    .try_into()
+                    .map_err(|_| Error::internal("invalid HUGEINT value"))?,
+            );
+            Ok(Kind::HugeintValue(proto::HugeInt::from(value)))
+        }
+        DataType::FixedSizeBinary(16) if extension_type(field) == Some("uhugeint") => {
+            let bytes = column.as_fixed_size_binary().value(row);
+            let value = u128::from_le_bytes(
+                bytes
+                    .try_into()
+                    .map_err(|_| Error::internal("invalid UHUGEINT value"))?,
+            );
+            Ok(Kind::UhugeintValue(proto::UHugeInt::from(value)))
+        }
+        DataType::Binary if matches!(extension_type(field), Some("varint" | "bignum")) => Ok(
+            Kind::StrValue(varint_text(column.as_binary::<i32>().value(row))?),
+        ),
+        DataType::LargeBinary if matches!(extension_type(field), Some("varint" | "bignum")) => Ok(
+            Kind::StrValue(varint_text(column.as_binary::<i64>().value(row))?),
+        ),
+        // BIT is passed in DuckDB's format as well as BLOB
+        DataType::Binary => Ok(Kind::BytesValue(
+            column.as_binary::<i32>().value(row).into(),
+        )),
+        DataType::LargeBinary => Ok(Kind::BytesValue(
+            column.as_binary::<i64>().value(row).into(),
+        )),
+        DataType::BinaryView => Ok(Kind::BytesValue(column.as_binary_view().value(row).into())),
+        DataType::FixedSizeBinary(_) if extension_type(field).is_none() => Ok(Kind::BytesValue(
+            column.as_fixed_size_binary().value(row).into(),
+        )),
+        DataType::List(element) => {
+            let list = column.as_list::<i32>();
+            Ok(Kind::ListValue(list_value(
+                element,
+                list.values().as_ref(),
+                list.value_offsets()[row] as usize..list.value_offsets()[row + 1] as usize,
+            )?))
+        }
+        DataType::LargeList(element) => {
+            let list = column.as_list::<i64>();
+            Ok(Kind::ListValue(list_value(
+                element,
+                list.values().as_ref(),
+                list.value_offsets()[row] as usize..list.value_offsets()[row + 1] as usize,
+            )?))
+        }
+        DataType::FixedSizeList(element, size) => {
+            let array = column.as_fixed_size_list();
+            let start = array.value_offset(row) as usize;
+            Ok(Kind::ArrayValue(list_value(
+                element,
+                array.values().as_ref(),
+                start..start + *size as usize,
+            )?))
+        }
+        DataType::Struct(fields) => {
+            let columns = column.as_struct().columns();
+            Ok(Kind::StructValue(proto::StructValue {
+                fields: fields
+                    .iter()
+                    .zip(columns)
+                    .map(|(field, column)| {
+                        Ok(proto::struct_value::Field {
+                            name: field.name().to_owned(),
+                            value: Some(proto::ScalarValue {
+                                kind: Some(value(field, column.as_ref(), row)?),
+                            }),
+                        })
+                    })
+                    .collect::<Result<Vec<proto::struct_value::Field>>>()?,
+            }))
+        }
+        DataType::Map(entries, _) => {
+            let map = column.as_map();
+            let (DataType::Struct(fields), [keys, values]) =
+                (entries.data_type(), map.entries().columns())
+            else {
+                return Err(Error::unsupported_type(column.data_type()));
+            };
+            let offsets = map.value_offsets();
+            Ok(Kind::MapValue(proto::MapValue {
+                entries: (offsets[row] as usize..offsets[row + 1] as usize)
+                    .map(|i| {
+                        Ok(proto::map_value::Entry {
+                            key: Some(proto::ScalarValue {
+                                kind: Some(value(&fields[0], keys.as_ref(), i)?),
+                            }),
+                            value: Some(proto::ScalarValue {
+                                kind: Some(value(&fields[1], values.as_ref(), i)?),
+                            }),
+                        })
+                    })
+                    .collect::<Result<Vec<proto::map_value::Entry>>>()?,
+            }))
+        }
+        // ENUM is passed as the indexes into all of its values
+        DataType::Dictionary(_, value_type) => {
+            let dictionary = column.as_any_dictionary();
+            let index = match value(field, dictionary.keys(), row)? {
+                Kind::UintValue(index) => index as usize,
+                Kind::IntValue(index) => index as usize,
+                _ => return Err(Error::internal("invalid ENUM index")),
+            };
+            let values = Field::new(field.name(), value_type.as_ref().clone(), true);
+            value(&values, dictionary.values().as_ref(), index)
+        }
+        DataType::Union(fields, _) => {
+            let union = column.as_union();
+            let type_id = union.type_id(row);
+            let (_, member) = fields
+                .iter()
+                .find(|(id, _)| *id == type_id)
+                .ok_or_else(|| Error::internal(format!("invalid UNION member {}", type_id)))?;
+            let kind = value(
+                member,
+                union.child(type_id).as_ref(),
+                union.value_offset(row),
+            )?;
+            if let Kind::NullValue(_) = kind {
+                return Ok(kind);
+            }
+            Ok(Kind::UnionValue(Box::new(proto::UnionValue {
+                tag: member.name().to_owned(),
+                value: Some(Box::new(proto::ScalarValue { kind: Some(kind) })),
+            })))
+        }
+        other => Err(Error::unsupported_type(other)),
+    }
+}
+
+fn list_value(
+    element: &Field,
+    values: &dyn Array,
+    range: std::ops::Range<usize>,
+) -> Result<proto::ListValue> {
+    Ok(proto::ListValue {
+        values: range
+            .map(|i| value(element, values, i).map(|kind| proto::ScalarValue { kind: Some(kind) }))
+            .collect::<Result<Vec<proto::ScalarValue>>>()?,
+    })
+}
+
+/// Schema of `interoperable` record batches.
+pub fn interoperable_schema(schema: &Schema) -> SchemaRef {
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| interoperable_field(field))
+        .collect::<Vec<Field>>();
+    std::sync::Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// Replaces HUGEINT, BOOLEAN and UUID columns and fields of nested columns, which the lossless
+/// conversion passes as extension types, with the Arrow types DuckDB uses by default so that
+/// other Arrow implementations can read them.
+pub fn interoperable(batch: &RecordBatch, schema: SchemaRef) -> Result<RecordBatch> {
+    let columns = batch
+        .schema()
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .map(|(field, column)| interoperable_column(field, column))
+        .collect::<Result<Vec<ArrayRef>>>()?;
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+fn interoperable_field(field: &Field) -> Field {
+    let data_type = match (extension_type(field), field.data_type()) {
+        (Some("hugeint"), _) => HUGEINT,
+        (Some("arrow.bool8"), _) => DataType::Boolean,
+        (Some("arrow.uuid"), _) => DataType::Utf8,
+        (Some(_), _) => return field.clone(),
+        (None, DataType::List(element)) => {
+            return field
+                .clone()
+                .with_data_type(DataType::List(interoperable_field(element).into()))
+        }
+        (None, DataType::LargeList(element)) => {
+            return field
+                .clone()
+                .with_data_type(DataType::LargeList(interoperable_field(element).into()))
+        }
+        (None, DataType::FixedSizeList(element, size)) => {
+            return field.clone().with_data_type(DataType::FixedSizeList(
+                interoperable_field(element).into(),
+                *size,
+            ))
+        }
+        (None, DataType::Struct(fields)) => {
+            return field.clone().with_data_type(DataType::Struct(
+                fields
+                    .iter()
+                    .map(|field| interoperable_field(field))
+                    .collect(),
+            ))
+        }
+        (None, DataType::Map(entries, sorted)) => {
+            return field
+                .clone()
+                .with_data_type(DataType::Map(interoperable_field(entries).into(), *sorted))
+        }
+        (None, DataType::Union(fields, mode)) => {
+            return field.clone().with_data_type(DataType::Union(
+                fields
+                    .iter()
+                    .map(|(type_id, field)| (type_id, interoperable_field(field).into()))
+                    .collect(),
+                *mode,
+            ))
+        }
+        (None, _) => return field.clone(),
+    };
+    Field::new(field.name(), data_type, field.is_nullable())
+}
+
+fn interoperable_column(field: &Field, column: &ArrayRef) -> Result<ArrayRef> {
+    use duckdb::arrow::array::{
+        FixedSizeListArray, LargeListArray, ListArray, MapArray, StructArray, UnionArray,
+    };
+
+    if interoperable_field(field) == *field {
+        return Ok(column.clone());
+    }
+    let column: ArrayRef = match (extension_type(field), field.data_type()) {
+        (Some("hugeint"), _) => std::sync::Arc::new(
+            column
+                .as_fixed_size_binary()
+                .iter()
+                .map(|value| {
+                    value
+                        .map(|bytes| bytes.try_into().map(i128::from_le_bytes))
+                        .transpose()
+                        .map_err(|_| Error::internal("invalid HUGEINT value"))
+                })
+                .collect::<Result<Decimal128Array>>()?
+                .with_data_type(HUGEINT),
+        ),
+        (Some("arrow.bool8"), _) => std::sync::Arc::new(
+            column
+                .as_primitive::<datatypes::Int8Type>()
+                .iter()
+                .map(|value| value.map(|value| value != 0))
+                .collect::<BooleanArray>(),
+        ),
+        (Some("arrow.uuid"), _) => std::sync::Arc::new(
+            column
+                .as_fixed_size_binary()
+                .iter()
+                .map(|value| value.map(uuid_text))
+                .collect::<StringArray>(),
+        ),
+        (None, DataType::List(element)) => {
+            let list = column.as_list::<i32>();
+            std::sync::Arc::new(ListArray::try_new(
+                interoperable_field(element).into(),
+                list.offsets().clone(),
+                interoperable_column(element, list.values())?,
+                list.nulls().cloned(),
+            )?)
+        }
+        (None, DataType::LargeList(element)) => {
+            let list = column.as_list::<i64>();
+            std::sync::Arc::new(LargeListArray::try_new(
+                interoperable_field(element).into(),
+                list.offsets().clone(),
+                interoperable_column(element, list.values())?,
+                list.nulls().cloned(),
+            )?)
+        }
+        (None, DataType::FixedSizeList(element, size)) => {
+            let list = column.as_fixed_size_list();
+            std::sync::Arc::new(FixedSizeListArray::try_new(
+                interoperable_field(element).into(),
+                *size,
+                interoperable_column(element, list.values())?,
+                list.nulls().cloned(),
+            )?)
+        }
+        (None, DataType::Struct(fields)) => {
+            let array = column.as_struct();
+            std::sync::Arc::new(StructArray::try_new(
+                fields
+                    .iter()
+                    .map(|field| interoperable_field(field))
+                    .collect(),
+                fields
+                    .iter()
+                    .zip(array.columns())
+                    .map(|(field, column)| interoperable_column(field, column))
+                    .collect::<Result<Vec<ArrayRef>>>()?,
+                array.nulls().cloned(),
+            )?)
+        }
+        (None, DataType::Map(entries, sorted)) => {
+            let map = column.as_map();
+            let columns: ArrayRef = std::sync::Arc::new(map.entries().clone());
+            std::sync::Arc::new(MapArray::try_new(
+                interoperable_field(entries).into(),
+                map.offsets().clone(),
+                interoperable_column(entries, &columns)?.as_struct().clone(),
+                map.nulls().cloned(),
+                *sorted,
+            )?)
+        }
+        (None, DataType::Union(fields, _)) => {
+            let union = column.as_union();
+            std::sync::Arc::new(UnionArray::try_new(
+                fields
+                    .iter()
+                    .map(|(type_id, field)| (type_id, interoperable_field(field).into()))
+                    .collect(),
+                union.type_ids().clone(),
+                union.offsets().cloned(),
+                fields
+                    .iter()
+                    .map(|(type_id, field)| interoperable_column(field, union.child(type_id)))
+                    .collect::<Result<Vec<ArrayRef>>>()?,
+            )?)
+        }
+        _ => column.clone(),
+    };
+    Ok(column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(sql: &str) -> RecordBatch {
+        let config = duckdb::Config::try_from(proto::Connect::default()).unwrap();
+        let conn = duckdb::Connection::open_in_memory_with_flags(config).unwrap();
+        let mut statement = conn.prepare(sql).unwrap();
+        let batch = statement.query_arrow([]).unwrap().next().unwrap();
+        batch
+    }
+
+    fn values(batch: &RecordBatch) -> Vec<Kind> {
+        rows(batch).unwrap()[0]
+            .values
+            .iter()
+            .map(|value| value.kind.clone().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn varint_as_text() {
+        let batch = query(
+            "SELECT '123456789012345678901234567890'::VARINT, (-1000000000)::VARINT, 0::VARINT",
+        );
+        assert_eq!(
+            values(&batch),
+            vec![
+                Kind::StrValue(String::from("123456789012345678901234567890")),
+                Kind::StrValue(String::from("-1000000000")),
+                Kind::StrValue(String::from("0")),
+            ]
+        );
+        assert_eq!(
+            proto::DataType::try_from(batch.schema().field(0)).unwrap(),
+            proto::DataType::DatatypeString
+        );
+    }
+
+    #[test]
+    fn bit_in_duckdb_format() {
+        // The first byte is the number of padding bits, which are set at the beginning of
+        // the second byte.
+        let batch = query("SELECT '1'::BIT, '10110'::BIT, '01000001'::BIT, '100000001'::BIT");
+        assert_eq!(
+            values(&batch),
+            vec![
+                Kind::BytesValue(vec![7, 0xFF]),
+                Kind::BytesValue(vec![3, 0xF6]),
+                Kind::BytesValue(vec![0, 0x41]),
+                Kind::BytesValue(vec![7, 0xFF, 0x01]),
+            ]
+        );
+        assert_eq!(
+            proto::DataType::try_from(batch.schema().field(0)).unwrap(),
+            proto::DataType::DatatypeBit
+        );
+    }
+
+    #[test]
+    fn json_as_text() {
+        // The bundled DuckDB has no json extension, so the column is built as DuckDB passes it.
+        let field = Field::new("j", DataType::Utf8, true).with_metadata(
+            [(
+                String::from("ARROW:extension:name"),
+                String::from("arrow.json"),
+            )]
+            .into(),
+        );
+        let batch = RecordBatch::try_new(
+            std::sync::Arc::new(Schema::new(vec![field])),
+            vec![std::sync::Arc::new(StringArray::from(vec!["{\"a\": [1, 2]}"])) as ArrayRef],
+        )
+        .unwrap();
+        assert_eq!(
+            values(&batch),
+            vec![Kind::StrValue(String::from("{\"a\": [1, 2]}"))]
+        );
+        let field = batch.schema().field(0).clone();
+        assert_eq!(
+            proto::DataType::try_from(&field).unwrap(),
+            proto::DataType::DatatypeString
+        );
+        assert_eq!(
+            proto::ColumnType::from_field(&field, None)
+                .unwrap()
+                .type_name,
+            "JSON"
+        );
+    }
+
+    #[test]
+    fn interoperable_nested_columns() {
+        let batch = query(
+            "SELECT [1::HUGEINT, NULL], \
+             {'u': 'a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11'::UUID, 'b': true}, \
+             MAP {'k': [false]}",
+        );
+        let schema = interoperable_schema(&batch.schema());
+        let batch = interoperable(&batch, schema.clone()).unwrap();
+
+        let DataType::List(element) = schema.field(0).data_type() else {
+            panic!("unexpected type {}", schema.field(0).data_type());
+        };
+        assert_eq!(element.data_type(), &HUGEINT);
+        let DataType::Struct(fields) = schema.field(1).data_type() else {
+            panic!("unexpected type {}", schema.field(1).data_type());
+        };
+        assert_eq!(fields[0].data_type(), &DataType::Utf8);
+        assert_eq!(fields[1].data_type(), &DataType::Boolean);
+        assert!(fields.iter().all(|field| extension_type(field).is_none()));
+        assert_eq!(batch.column(0).as_list::<i32>().value(0).len(), 2);
+        assert_eq!(
+            batch
+                .column(1)
+                .as_struct()
+                .column(0)
+                .as_string::<i32>()
+                .value(0),
+            "a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11"
+        );
+        let entries = batch.column(2).as_map().entries();
+        assert!(!entries
+            .column(1)
+            .as_list::<i32>()
+            .value(0)
+            .as_boolean()
+            .value(0));
+    }
+}